@@ -1,24 +1,30 @@
 use miniquad::*;
 
-use glam::{vec2, vec3, Vec2};
+use glam::{vec2, vec4, Vec2};
 use lines::{
     camera::Camera,
-    draw_lines::{Line, Lines, LinesRenderer, SegmentType},
+    draw_lines::{LineHandle, LineStyle, LinesRenderer, WidthMode},
+    dvec2::{dvec2, DVec2},
+    lod::LodPyramid,
+    pick::Picked,
+    tiles::TileSet,
 };
 
-pub const MAP_SIZE: i32 = 11;
+/// half of Web Mercator world width in meters
+pub const MAP_SIZE: f32 = 20_037_508.;
 
 pub struct Mouse {
     pub left_down: bool,
     pub last_left_down: Vec2,
-    pub position: Vec2,
+    /// in f64, meters far from the origin don't fit into f32
+    pub position: DVec2,
     pub position_screen: Vec2,
 }
 
 impl Mouse {
     pub fn update(&mut self, pos: Vec2, camera: &Camera, width: f32, height: f32) {
         self.position_screen = pos;
-        self.position = camera.unproject(pos.x(), pos.y(), width, height);
+        self.position = camera.unproject_f64(pos.x(), pos.y(), width, height);
     }
 }
 
@@ -27,7 +33,7 @@ impl Default for Mouse {
         Mouse {
             left_down: false,
             last_left_down: vec2(0., 0.),
-            position: vec2(0., 0.),
+            position: DVec2::zero(),
             position_screen: vec2(0., 0.),
         }
     }
@@ -37,63 +43,99 @@ struct Stage {
     mouse: Mouse,
     lines_renderer: LinesRenderer,
     camera: Camera,
-    lines: Lines,
-    linestrings: Vec<Vec<Vec2>>,
+    start_time: f64,
+    /// tile line under the cursor (index in level 0) and its highlight in renderer lines
+    hovered: Option<(usize, Vec<LineHandle>)>,
 }
 
 impl Stage {
     pub fn new(ctx: &mut Context) -> Stage {
         let max_lines = 3_000_000;
-        let lines_renderer = LinesRenderer::new(ctx, max_lines);
-        let mut linestrings = vec![];
-        let lines = lines_renderer.create_lines();
-        let mut max_x = 0.;
-        let mut max_y = 0.;
+        // the map is in tiles with their own buffers, renderer lines are for overlays
+        let mut lines_renderer = LinesRenderer::new(ctx, 1024);
+        lines_renderer.width_mode = WidthMode::Pixels;
+        let mut linestrings: Vec<Vec<DVec2>> = vec![];
+        // from 1 m to 2 km, zoomed out map draws the coarse levels
+        let mut lod = LodPyramid::new(1., 12);
         use std::fs::File;
         use std::io::BufReader;
         let camera = {
             let f = File::open("random.txt").unwrap();
             let f = BufReader::new(f);
             use std::io::prelude::*;
-            let mut point_sum = vec2(0., 0.);
+            // raw Web Mercator coordinates, renderer keeps them in f64
+            let mut point_sum = DVec2::zero();
             let mut point_cnt = 0;
             'lines: for line in f.lines() {
                 let mut points = vec![];
                 let line = line.unwrap();
                 let numbers: Vec<_> = line.split(" ").collect();
                 for i in 0..numbers.len() / 2 {
-                    let point = vec2(
+                    let point = dvec2(
                         numbers[i * 2].parse().unwrap(),
                         numbers[i * 2 + 1].parse().unwrap(),
                     );
                     points.push(point);
                     point_sum += point;
                     point_cnt += 1;
-                    max_x = if point.x() > max_x { point.x() } else { max_x };
-                    max_y = if point.y() > max_y { point.y() } else { max_y };
                     if point_cnt >= max_lines {
                         break 'lines;
                     }
                 }
                 linestrings.push(points);
             }
-            for i in linestrings.iter_mut() {
-                for j in i.iter_mut() {
-                    *j.x_mut() = j.x() / max_x;
-                    *j.y_mut() = j.y() / max_y;
-                }
+            // dashes show the direction of the roads
+            let style = LineStyle::new(0.7, vec4(0., 0., 0., 1.))
+                .with_dash(&[6., 4.], 0.)
+                .with_flow(10.);
+            for points in linestrings.iter() {
+                lod.add_polyline(points, style);
             }
-            point_sum = vec2(point_sum.x() / max_x, point_sum.y() / max_y);
-            let mut camera = Camera::new(10., 0.001);
-            camera.position_set(point_sum / point_cnt as f32, 20. * MAP_SIZE as f32);
+            // a few kilometers on the screen, zoom is in 1 / meters
+            let mut camera = Camera::new(1. / 5000., 1. / 50000.);
+            camera.min_zoom = 1. / 1_000_000.;
+            camera.position_set(point_sum / point_cnt as f64, MAP_SIZE);
             camera
         };
+        lines_renderer.set_tiles(Some(TileSet::new(lod)));
         Stage {
             lines_renderer,
             camera,
-            lines,
             mouse: Mouse::default(),
-            linestrings,
+            start_time: date::now(),
+            hovered: None,
+        }
+    }
+
+    /// Draws the road segment under the cursor in red over the tiles
+    fn hover(&mut self, width: f32, height: f32) {
+        let picked = self
+            .lines_renderer
+            .pick(&self.camera, width, height, self.mouse.position, 3.)
+            .into_iter()
+            .find_map(|picked| match picked {
+                Picked::Tile(index) => Some(index),
+                // the highlight itself
+                Picked::Line(_) => None,
+            });
+        if picked == self.hovered.as_ref().map(|(index, _)| *index) {
+            return;
+        }
+        if let Some((_, handles)) = self.hovered.take() {
+            for handle in handles {
+                self.lines_renderer.lines.remove(handle);
+            }
+        }
+        if let Some(index) = picked {
+            let tiles = self.lines_renderer.tiles().unwrap();
+            let (line, center) = tiles.lod().line(0, index);
+            let half: DVec2 = (line.dir / 2.).into();
+            let style = LineStyle::new(line.thickness + 1., vec4(1., 0., 0., 1.));
+            let handles = self
+                .lines_renderer
+                .lines
+                .add_polyline(&[center - half, center + half], style);
+            self.hovered = Some((index, handles));
         }
     }
 }
@@ -118,13 +160,15 @@ impl EventHandler for Stage {
 
     fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32) {
         let (width, height) = ctx.screen_size();
+        self.mouse.update(vec2(x, y), &self.camera, width, height);
+        self.hover(width, height);
         if self.mouse.left_down {
             let pos = vec2(x, y);
             let mut delta = (pos - self.mouse.last_left_down) / self.camera.zoom;
-            delta.set_x(-1. * delta.x());
+            delta.set_x(-delta.x());
             delta.set_x(delta.x() / width);
             delta.set_y(delta.y() / height / 2.); // why /2. ??
-            self.camera.position_add(delta, 20. * MAP_SIZE as f32);
+            self.camera.position_add(delta, MAP_SIZE);
             self.mouse.last_left_down = pos;
         }
     }
@@ -136,34 +180,14 @@ impl EventHandler for Stage {
     }
 
     fn update(&mut self, _ctx: &mut Context) {
-        self.lines.clear();
-        for points in self.linestrings.iter() {
-            let mut prev = points[0];
-            for (i, point) in points.into_iter().enumerate() {
-                self.lines.add(Line::new(
-                    match i {
-                        0 => SegmentType::All,
-                        _ => SegmentType::NoFirst,
-                    },
-                    prev,
-                    *point,
-                    (0.0005 / self.camera.zoom).min(0.00005).max(0.0000001),
-                    vec3(0., 0., 0.), // vec3(qrand::gen_range(0.5, 1.), qrand::gen_range(0., 1.), qrand::gen_range(0., 1.))
-                ));
-                prev = *point;
-            }
-        }
-
+        self.lines_renderer.time = (date::now() - self.start_time) as f32;
         self.camera.update()
     }
 
     fn draw(&mut self, ctx: &mut Context) {
-        let (width, height) = ctx.screen_size();
         // dbg!(self.camera.get_mvp(height / width).to_cols_array_2d()[0][0], self.camera.zoom);
         // dbg!(self.camera.get_mvp(height / width).to_cols_array_2d()[0][0] /self.camera.zoom);
         ctx.begin_default_pass(PassAction::clear_color(1., 0.98, 200. / 255., 1.));
-        self.lines_renderer.clear_buffers();
-        self.lines_renderer.push_segments(ctx, self.lines.clone());
         self.lines_renderer.draw(ctx, &self.camera);
         ctx.end_render_pass();
         ctx.commit_frame();
@@ -1,9 +1,14 @@
 use miniquad::*;
 
-use glam::{vec2, vec3, Vec2};
+use glam::{vec2, vec4, Vec2};
 use lines::{
     camera::Camera,
-    draw_lines::{Line, Lines, LinesRenderer, SegmentType},
+    colormap::Colormap,
+    draw_lines::{Line, LineHandle, LineStyle, LinesRenderer},
+    pdf::{Page, Pdf},
+    pick::Picked,
+    screenshot::Screenshot,
+    svg::Svg,
 };
 use quad_rand as qrand;
 
@@ -38,43 +43,67 @@ struct Stage {
     mouse: Mouse,
     lines_renderer: LinesRenderer,
     camera: Camera,
-    lines: Lines,
+    screenshot: Screenshot,
+    /// segment under the cursor and how it looked before highlighting
+    hovered: Option<(LineHandle, Line)>,
 }
 
 impl Stage {
     pub fn new(ctx: &mut Context) -> Stage {
         let stringline_num = 100;
-        let lines_renderer = LinesRenderer::new(ctx, stringline_num);
+        let mut lines_renderer = LinesRenderer::new(ctx, stringline_num);
         let mut lines = lines_renderer.create_lines();
         let camera = {
             let mut point_sum = vec2(0., 0.);
             let mut point_cnt = 0;
-            let mut prev = vec2(0., 0.);
-            let color = vec3(
-                qrand::gen_range(0.5, 1.),
-                qrand::gen_range(0., 1.),
-                qrand::gen_range(0., 1.),
-            );
+            let mut points = vec![];
+            let mut values = vec![];
             for i in 0..stringline_num {
                 let point = vec2(qrand::gen_range(-100., 100.), qrand::gen_range(-100., 100.));
                 point_sum += point;
                 point_cnt += 1;
-                let segment_type = match i {
-                    0 => SegmentType::All,
-                    _ => SegmentType::NoFirst,
-                };
-                lines.add(Line::new(segment_type, prev, point, 1.1, color));
-                prev = point;
+                points.push(point);
+                values.push(i as f32);
             }
+            let colormap = Colormap::Viridis.scaled(0., stringline_num as f32);
+            let style = LineStyle::new(1.1, vec4(0., 0., 0., 1.));
+            lines.add_colormapped_polyline(&points, &values, colormap, style);
             let mut camera = Camera::new(0.004, 0.001);
             camera.position_set(point_sum / point_cnt as f32, 20. * MAP_SIZE as f32);
             camera
         };
+        lines_renderer.push_segments(ctx, lines);
         Stage {
             lines_renderer,
             camera,
-            lines,
             mouse: Mouse::default(),
+            screenshot: Screenshot::new(ctx, 3840, 2160, vec4(1., 0.98, 200. / 255., 1.)),
+            hovered: None,
+        }
+    }
+
+    /// Outlines the segment under the cursor
+    fn hover(&mut self, width: f32, height: f32) {
+        let picked = self
+            .lines_renderer
+            .pick(&self.camera, width, height, self.mouse.position, 3.)
+            .into_iter()
+            .find_map(|picked| match picked {
+                Picked::Line(handle) => Some(handle),
+                // there are no tiles here
+                Picked::Tile(_) => None,
+            });
+        if picked == self.hovered.map(|(handle, _)| handle) {
+            return;
+        }
+        let lines = &mut self.lines_renderer.lines;
+        if let Some((handle, line)) = self.hovered.take() {
+            lines.update(handle, line);
+        }
+        if let Some(handle) = picked {
+            let line = *lines.get(handle).unwrap();
+            lines.update(handle, line.with_outline(1., vec4(1., 0., 0., 1.)));
+            self.hovered = Some((handle, line));
         }
     }
 }
@@ -99,10 +128,12 @@ impl EventHandler for Stage {
 
     fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32) {
         let (width, height) = ctx.screen_size();
+        self.mouse.update(vec2(x, y), &self.camera, width, height);
+        self.hover(width, height);
         if self.mouse.left_down {
             let pos = vec2(x, y);
             let mut delta = (pos - self.mouse.last_left_down) / self.camera.zoom;
-            delta.set_x(-1. * delta.x());
+            delta.set_x(-delta.x());
             delta.set_x(delta.x() / width);
             delta.set_y(delta.y() / height / 2.); // why /2. ??
             self.camera.position_add(delta, 20. * MAP_SIZE as f32);
@@ -110,6 +141,41 @@ impl EventHandler for Stage {
         }
     }
 
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        _keymods: KeyMods,
+        _repeat: bool,
+    ) {
+        // 4K screenshot regardless of the window size
+        if keycode == KeyCode::S {
+            let image = self
+                .screenshot
+                .take(ctx, &mut self.lines_renderer, &self.camera);
+            image.save_png("screenshot.png").unwrap();
+        }
+        // vector picture of the current view
+        if keycode == KeyCode::V {
+            let (width, height) = ctx.screen_size();
+            let view = self.camera.view_bounds(width, height);
+            let svg = Svg::from_renderer(&self.lines_renderer).export(
+                &self.lines_renderer.lines,
+                view,
+                width,
+                height,
+            );
+            std::fs::write("lines.svg", svg).unwrap();
+        }
+        if keycode == KeyCode::P {
+            let (width, height) = ctx.screen_size();
+            let view = self.camera.view_bounds(width, height);
+            let mut pdf = Pdf::from_renderer(&self.lines_renderer);
+            pdf.add_page(Page::A4.landscape(), &self.lines_renderer.lines, view);
+            pdf.save("lines.pdf").unwrap();
+        }
+    }
+
     fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
         let y = y.signum() * 1.2;
         self.camera.zoom_wheel(y);
@@ -125,8 +191,6 @@ impl EventHandler for Stage {
         // dbg!(self.camera.get_mvp(height / width).to_cols_array_2d()[0][0], self.camera.zoom);
         // dbg!(self.camera.get_mvp(height / width).to_cols_array_2d()[0][0] /self.camera.zoom);
         ctx.begin_default_pass(PassAction::clear_color(1., 0.98, 200. / 255., 1.));
-        self.lines_renderer.clear_buffers();
-        self.lines_renderer.push_segments(ctx, self.lines.clone());
         self.lines_renderer.draw(ctx, &self.camera);
         ctx.end_render_pass();
         ctx.commit_frame();
@@ -1,4 +1,7 @@
 use glam::{vec2, vec3, vec4, Mat4, Vec2};
+
+use crate::dvec2::{dvec2, DVec2};
+
 pub const MAX_ZOOM: f32 = 100000.0;
 pub const INIT_ZOOM: f32 = 0.1;
 pub const MIN_ZOOM: f32 = 0.001;
@@ -10,10 +13,14 @@ fn lerp<T: Mul<f32, Output = T> + Add<T, Output = T> + Clone>(a: T, b: T, t: f32
 }
 
 pub struct Camera {
-    pub desired_position: Vec2,
-    pub position2d: Vec2,
+    /// f64, so the camera can move smoothly over big world coordinates
+    pub desired_position: DVec2,
+    pub position2d: DVec2,
     pub desired_zoom: f32,
     pub zoom: f32,
+    /// zoom_set and zoom_wheel keep zoom in [min_zoom, max_zoom]
+    pub min_zoom: f32,
+    pub max_zoom: f32,
 }
 
 impl Camera {
@@ -25,7 +32,9 @@ impl Camera {
         }
     }
 
-    pub fn get_mvp(&self, aspect_ratio: f32) -> Mat4 {
+    /// Projection of positions relative to the camera eye.
+    /// Renderer uses it with positions rebased on CPU, so there are no big numbers on GPU
+    pub fn get_projection(&self, aspect_ratio: f32) -> Mat4 {
         let w = 1. / self.zoom;
         let h = aspect_ratio / self.zoom;
         let proj = Mat4::orthographic_rh_gl(
@@ -36,55 +45,57 @@ impl Camera {
             1.,      // near
             0.,      // far
         );
-        let eye = vec3(self.position2d.x(), self.position2d.y(), 1.);
-        let center = vec3(self.position2d.x(), self.position2d.y(), 0.0);
+        let eye = vec3(0., 0., 1.);
+        let center = vec3(0., 0., 0.0);
         let up = vec3(0.0, 1.0, 0.0);
         let view = Mat4::look_at_rh(eye, center, up);
         proj * view
     }
 
+    /// Projection of world positions, loses precision when camera is far from the origin
+    pub fn get_mvp(&self, aspect_ratio: f32) -> Mat4 {
+        let eye = self.position2d.as_vec2();
+        self.get_projection(aspect_ratio) * Mat4::from_translation(vec3(-eye.x(), -eye.y(), 0.))
+    }
+
     pub fn update(&mut self) {
         self.zoom = lerp(self.zoom, self.desired_zoom, 0.8);
-        self.position2d = lerp(self.position2d, self.desired_position, 0.4);
+        self.position2d = self.position2d * 0.4 + self.desired_position * 0.6;
     }
 
     fn position_restrictions(&mut self, map_size: f32) {
-        *self.desired_position.x_mut() = self
-            .desired_position
-            .x()
-            .min(map_size as f32)
-            .max(-map_size as f32);
-        *self.desired_position.y_mut() = self
-            .desired_position
-            .y()
-            .min(map_size as f32)
-            .max(-map_size as f32);
+        let map_size = map_size as f64;
+        self.desired_position = dvec2(
+            self.desired_position.x.clamp(-map_size, map_size),
+            self.desired_position.y.clamp(-map_size, map_size),
+        );
     }
 
-    pub fn position_set(&mut self, value: Vec2, map_size: f32) {
-        self.desired_position = value;
+    pub fn position_set<P: Into<DVec2>>(&mut self, value: P, map_size: f32) {
+        self.desired_position = value.into();
         self.position_restrictions(map_size);
     }
 
     pub fn position_add(&mut self, delta: Vec2, map_size: f32) {
-        self.desired_position += delta;
+        self.desired_position += delta.into();
         self.position_restrictions(map_size);
     }
 
     pub fn zoom_set(&mut self, zoom: f32) {
         self.desired_zoom = zoom;
-        self.desired_zoom = self.desired_zoom.min(MAX_ZOOM).max(MIN_ZOOM);
+        self.desired_zoom = self.desired_zoom.clamp(self.min_zoom, self.max_zoom);
     }
 
     pub fn zoom_wheel(&mut self, y: f32) {
         self.desired_zoom *= f32::powf(1.2, y);
-        self.desired_zoom = self.desired_zoom.min(MAX_ZOOM).max(MIN_ZOOM);
+        self.desired_zoom = self.desired_zoom.clamp(self.min_zoom, self.max_zoom);
     }
 
     /// use only if it's needed once, cause it creates project matrix inside
-    pub fn project(&self, point: Vec2, width: f32, height: f32) -> Vec2 {
-        let mvp = self.get_mvp(height / width);
-        let projected = mvp * vec4(point.x(), point.y(), 0., 1.);
+    pub fn project<P: Into<DVec2>>(&self, point: P, width: f32, height: f32) -> Vec2 {
+        let projection = self.get_projection(height / width);
+        let point = (point.into() - self.position2d).as_vec2();
+        let projected = projection * vec4(point.x(), point.y(), 0., 1.);
         vec2(
             (projected.x() + 1.) * width / 2.,
             (1. - projected.y()) * height / 2.,
@@ -93,22 +104,36 @@ impl Camera {
 
     /// x, y -- screen coordinates in pixels
     pub fn unproject(&self, x: f32, y: f32, width: f32, height: f32) -> Vec2 {
+        self.unproject_f64(x, y, width, height).as_vec2()
+    }
+
+    /// unproject without precision loss far from the origin
+    pub fn unproject_f64(&self, x: f32, y: f32, width: f32, height: f32) -> DVec2 {
         // coords are in cube with corners [-1, -1, -1], [1, 1, 1] after orthographic projection
         let sx = -1. + 2. * x / width;
         let sy = 1. - 2. * y / height;
         // apply inverse matrix to point on a surface
-        let unproject_pos = self.get_mvp(height / width).inverse() * vec4(sx, sy, 0., 1.);
-        vec2(unproject_pos.x(), unproject_pos.y())
+        let unproject_pos = self.get_projection(height / width).inverse() * vec4(sx, sy, 0., 1.);
+        self.position2d + vec2(unproject_pos.x(), unproject_pos.y()).into()
+    }
+
+    /// World rectangle (min, max) visible on the screen of width x height pixels
+    pub fn view_bounds(&self, width: f32, height: f32) -> (DVec2, DVec2) {
+        let top_left = self.unproject_f64(0., 0., width, height);
+        let bottom_right = self.unproject_f64(width, height, width, height);
+        (top_left.min(bottom_right), top_left.max(bottom_right))
     }
 }
 
 impl Default for Camera {
     fn default() -> Self {
         Camera {
-            desired_position: vec2(0., 0.),
-            position2d: vec2(0., 0.),
+            desired_position: DVec2::zero(),
+            position2d: DVec2::zero(),
             desired_zoom: INIT_ZOOM,
             zoom: MIN_ZOOM,
+            min_zoom: MIN_ZOOM,
+            max_zoom: MAX_ZOOM,
         }
     }
 }
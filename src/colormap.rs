@@ -0,0 +1,141 @@
+// Colors for data: continuous colormaps take value in [0, 1], categorical palettes take index.
+// Everything returns RGBA with alpha 1, so the result can go straight to Line::new
+
+use glam::{vec3, vec4, Vec3, Vec4};
+
+/// d3 category10 (Tableau 10)
+pub const CATEGORY10: &[u32] = &[
+    0x1f77b4, 0xff7f0e, 0x2ca02c, 0xd62728, 0x9467bd, 0x8c564b, 0xe377c2, 0x7f7f7f, 0xbcbd22,
+    0x17becf,
+];
+
+/// ColorBrewer Set1
+pub const SET1: &[u32] = &[
+    0xe41a1c, 0x377eb8, 0x4daf4a, 0x984ea3, 0xff7f00, 0xffff33, 0xa65628, 0xf781bf, 0x999999,
+];
+
+/// ColorBrewer Dark2
+pub const DARK2: &[u32] = &[
+    0x1b9e77, 0xd95f02, 0x7570b3, 0xe7298a, 0x66a61e, 0xe6ab02, 0xa6761d, 0x666666,
+];
+
+/// 0xRRGGBB to color
+pub fn rgb(hex: u32) -> Vec3 {
+    vec3(
+        ((hex >> 16) & 0xff) as f32 / 255.,
+        ((hex >> 8) & 0xff) as f32 / 255.,
+        (hex & 0xff) as f32 / 255.,
+    )
+}
+
+/// Palette color for index, palette is repeated if there are more categories than colors
+pub fn categorical(palette: &[u32], index: usize) -> Vec4 {
+    rgb(palette[index % palette.len()]).extend(1.)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Turbo,
+}
+
+impl Colormap {
+    /// t is clamped to [0, 1]
+    pub fn color(self, t: f32) -> Vec4 {
+        match self {
+            Colormap::Viridis => viridis(t),
+            Colormap::Magma => magma(t),
+            Colormap::Turbo => turbo(t),
+        }
+    }
+
+    /// Colormap stretched over [min, max] of the data, for `Lines::add_colormapped_polyline`
+    pub fn scaled(self, min: f32, max: f32) -> impl Fn(f32) -> Vec4 {
+        move |value| self.color(normalize(value, min, max))
+    }
+}
+
+/// Maps value from [min, max] to [0, 1] with clamping, degenerate range gives 0.5.
+/// `min` above `max` flips the colormap
+pub fn normalize(value: f32, min: f32, max: f32) -> f32 {
+    if max == min {
+        return 0.5;
+    }
+    ((value - min) / (max - min)).clamp(0., 1.)
+}
+
+/// Min and max of data ignoring NaNs, None if there is no numbers
+pub fn data_range(values: &[f32]) -> Option<(f32, f32)> {
+    values
+        .iter()
+        .filter(|value| !value.is_nan())
+        .fold(None, |range, value| match range {
+            None => Some((*value, *value)),
+            Some((min, max)) => Some((value.min(min), value.max(max))),
+        })
+}
+
+// polynomial fits of matplotlib colormaps (https://www.shadertoy.com/view/WlfXRN)
+fn polynomial(t: f32, c: &[[f32; 3]; 7]) -> Vec4 {
+    let t = t.clamp(0., 1.);
+    let mut res = Vec3::zero();
+    for coefficient in c.iter().rev() {
+        res = res * t + vec3(coefficient[0], coefficient[1], coefficient[2]);
+    }
+    vec4(
+        res.x().clamp(0., 1.),
+        res.y().clamp(0., 1.),
+        res.z().clamp(0., 1.),
+        1.,
+    )
+}
+
+#[rustfmt::skip]
+const VIRIDIS: [[f32; 3]; 7] = [
+    [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+    [0.105_093_04, 1.404_613_5, 1.384_590_1],
+    [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+    [-4.634_230_4, -5.799_101, -19.332_441],
+    [6.228_27, 14.179_933, 56.690_55],
+    [4.776_385, -13.745_145, -65.353_03],
+    [-5.435_456, 4.645_852_6, 26.312_435],
+];
+
+#[rustfmt::skip]
+const MAGMA: [[f32; 3]; 7] = [
+    [-0.002_136_485, -0.000_749_655, -0.005_386_128],
+    [0.251_660_54, 0.677_523_24, 2.494_026_7],
+    [8.353_717, -3.577_719_5, 0.314_467_9],
+    [-27.668_733, 14.264_731, -13.649_213],
+    [52.176_14, -27.943_607, 12.944_169],
+    [-50.768_524, 29.046_583, 4.234_153],
+    [18.655_705, -11.489_773, -5.601_961_5],
+];
+
+// polynomial approximation of Google Turbo (https://ai.googleblog.com/2019/08/turbo-improved-rainbow-colormap-for.html)
+#[rustfmt::skip]
+const TURBO: [[f32; 3]; 7] = [
+    [0.135_721_38, 0.091_402_61, 0.106_673_3],
+    [4.615_392_6, 2.194_188_4, 12.641_946],
+    [-42.660_324, 4.842_966_6, -60.582_05],
+    [132.131_08, -14.185_033, 110.362_77],
+    [-152.942_4, 4.277_299, -89.903_11],
+    [59.286_38, 2.829_566, 27.348_25],
+    [0., 0., 0.],
+];
+
+/// Perceptually uniform, dark blue to yellow
+pub fn viridis(t: f32) -> Vec4 {
+    polynomial(t, &VIRIDIS)
+}
+
+/// Perceptually uniform, black to light yellow through purple
+pub fn magma(t: f32) -> Vec4 {
+    polynomial(t, &MAGMA)
+}
+
+/// Rainbow-like, dark blue to dark red, good for showing detail but not perceptually uniform
+pub fn turbo(t: f32) -> Vec4 {
+    polynomial(t, &TURBO)
+}
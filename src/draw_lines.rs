@@ -1,10 +1,19 @@
 // experiment drawing lines with instancing. Transform is attribute params
 // probably it's not faster
 
-use glam::{Vec2, Vec3};
+use glam::{vec2, Vec2, Vec4};
 use miniquad::*;
 
+use std::collections::BTreeMap;
+use std::ops::Range;
+
 use crate::camera::Camera;
+use crate::dvec2::DVec2;
+use crate::lod::LodPyramid;
+use crate::pick::{line_at, pick_from, Picked};
+use crate::raster::Raster;
+use crate::spatial::SpatialIndex;
+use crate::tiles::TileSet;
 
 #[rustfmt::skip]
 pub const RECT: &[f32] = &[
@@ -15,6 +24,9 @@ pub const RECT: &[f32] = &[
     -1.1, -1.,
 ];
 
+/// Lines in one GPU instance buffer, renderer allocates more buffers when needed
+pub const CHUNK_LINES: usize = 16384;
+
 #[rustfmt::skip]
 const RECT_INDICES: &[u16] = &[
     0, 1, 2, 
@@ -23,6 +35,7 @@ const RECT_INDICES: &[u16] = &[
     0, 4, 1
 ];
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum SegmentType {
     All = 0,
@@ -31,6 +44,57 @@ pub enum SegmentType {
     NoAll = 3,
 }
 
+/// Shape of the segment end which is not connected to another segment
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum LineCap {
+    /// Flat end exactly at the segment end
+    Butt = 0,
+    /// Flat end sticking out by the half of line width
+    Square = 1,
+    Round = 2,
+}
+
+/// How consecutive segments of a polyline are connected
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    /// Sharp corner. Falls back to bevel when miter length / line width exceeds the limit (like in SVG)
+    Miter(f32),
+    #[default]
+    Round,
+    Bevel,
+}
+
+impl LineJoin {
+    pub(crate) fn as_uniform(self) -> (f32, f32) {
+        match self {
+            LineJoin::Miter(limit) => (0., limit),
+            LineJoin::Round => (1., 1.),
+            LineJoin::Bevel => (2., 1.),
+        }
+    }
+}
+
+/// Units of `Line::thickness` (distance from the center line to the edge)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WidthMode {
+    #[default]
+    World,
+    /// Thickness is in screen pixels, so lines keep their width while zooming
+    Pixels,
+}
+
+/// Float precision of the shaders. Lower precision may be faster on mobile GPUs,
+/// but gives jitter and banding at high zoom. Desktop GPUs usually ignore it
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Precision {
+    Low,
+    Medium,
+    /// Falls back to medium in fragment shader if GPU doesn't support high precision there
+    #[default]
+    High,
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct Line {
@@ -38,7 +102,27 @@ pub struct Line {
     pub position: Vec2,
     pub thickness: f32,
     pub dir: Vec2,
-    pub color: Vec3,
+    /// RGBA, alpha is multiplied with antialiasing coverage
+    pub color: Vec4,
+    /// direction of the previous segment in polyline, zero if there is no one
+    pub prev_dir: Vec2,
+    /// direction of the next segment in polyline, zero if there is no one
+    pub next_dir: Vec2,
+    /// LineCap at the start and at the end of the segment
+    pub caps: Vec2,
+    /// dash pattern (dash, gap, dash, gap) in the same units as thickness, zeros for solid line
+    pub dash: Vec4,
+    /// distance along the polyline to the segment start (world units), so dashes are continuous
+    pub distance: f32,
+    /// shift of the dash pattern along the polyline
+    pub dash_offset: f32,
+    /// speed of dashes moving along the segment direction, thickness units per second
+    pub flow_speed: f32,
+    /// color at the segment end, it's interpolated from `color` along the segment
+    pub end_color: Vec4,
+    /// width of the casing around the line, in the same units as thickness
+    pub outline_width: f32,
+    pub outline_color: Vec4,
 }
 
 impl Line {
@@ -47,7 +131,7 @@ impl Line {
         from: Vec2,
         to: Vec2,
         thickness: f32,
-        color: Vec3,
+        color: Vec4,
     ) -> Self {
         let dir = to - from;
         Line {
@@ -56,60 +140,584 @@ impl Line {
             thickness,
             dir,
             color,
+            prev_dir: Vec2::zero(),
+            next_dir: Vec2::zero(),
+            caps: vec2(LineCap::Round as u8 as f32, LineCap::Round as u8 as f32),
+            dash: Vec4::zero(),
+            distance: 0.,
+            dash_offset: 0.,
+            flow_speed: 0.,
+            end_color: color,
+            outline_width: 0.,
+            outline_color: Vec4::zero(),
+        }
+    }
+
+    pub fn with_end_color(mut self, end_color: Vec4) -> Self {
+        self.end_color = end_color;
+        self
+    }
+
+    pub fn with_outline(mut self, outline_width: f32, outline_color: Vec4) -> Self {
+        self.outline_width = outline_width;
+        self.outline_color = outline_color;
+        self
+    }
+
+    /// Caps are drawn only on the ends without neighbours
+    pub fn with_caps(mut self, start: LineCap, end: LineCap) -> Self {
+        self.caps = vec2(start as u8 as f32, end as u8 as f32);
+        self
+    }
+
+    /// Dashes get the start cap of the segment
+    pub fn with_dash(mut self, dash: Vec4, dash_offset: f32) -> Self {
+        self.dash = dash;
+        self.dash_offset = dash_offset;
+        self
+    }
+
+    pub fn with_flow(mut self, flow_speed: f32) -> Self {
+        self.flow_speed = flow_speed;
+        self
+    }
+
+    pub fn with_distance(mut self, distance: f32) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    /// Connect segment with its neighbours, so the renderer draws joins instead of caps.
+    /// Zero direction means there is no neighbour on that end.
+    pub fn with_neighbours(mut self, prev_dir: Vec2, next_dir: Vec2) -> Self {
+        self.prev_dir = prev_dir;
+        self.next_dir = next_dir;
+        self
+    }
+}
+
+/// Line as it's uploaded to GPU. It's 7 vec4 attributes, so with the quad corner the pipeline
+/// fits into 8 attributes of GLES2 and WebGL1
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub(crate) struct GpuLine {
+    /// position relative to the chunk origin and dir
+    segment: Vec4,
+    /// segment_type + 4 * start cap + 16 * end cap, thickness, outline_width, distance
+    params: Vec4,
+    /// dash_offset, flow_speed, angles of prev_dir and next_dir
+    path: Vec4,
+    color: Vec4,
+    dash: Vec4,
+    end_color: Vec4,
+    outline_color: Vec4,
+}
+
+/// angle of the missing neighbour, it's out of [-pi, pi]
+const NO_NEIGHBOUR: f32 = 8.;
+
+impl GpuLine {
+    pub(crate) fn new(line: &Line, position: Vec2) -> Self {
+        let angle = |dir: Vec2| {
+            if dir.dot(dir) > 0. {
+                dir.y().atan2(dir.x())
+            } else {
+                NO_NEIGHBOUR
+            }
+        };
+        let kind = line.segment_type + 4. * line.caps.x() + 16. * line.caps.y();
+        GpuLine {
+            segment: Vec4::new(position.x(), position.y(), line.dir.x(), line.dir.y()),
+            params: Vec4::new(kind, line.thickness, line.outline_width, line.distance),
+            path: Vec4::new(
+                line.dash_offset,
+                line.flow_speed,
+                angle(line.prev_dir),
+                angle(line.next_dir),
+            ),
+            color: line.color,
+            dash: line.dash,
+            end_color: line.end_color,
+            outline_color: line.outline_color,
+        }
+    }
+}
+
+/// Style shared by all segments of a polyline
+#[derive(Debug, Clone, Copy)]
+pub struct LineStyle {
+    pub thickness: f32,
+    pub color: Vec4,
+    /// cap on both ends of an open polyline and on every dash
+    pub cap: LineCap,
+    pub dash: Vec4,
+    pub dash_offset: f32,
+    pub flow_speed: f32,
+    pub outline_width: f32,
+    pub outline_color: Vec4,
+}
+
+impl LineStyle {
+    pub fn new(thickness: f32, color: Vec4) -> Self {
+        LineStyle {
+            thickness,
+            color,
+            cap: LineCap::Round,
+            dash: Vec4::zero(),
+            dash_offset: 0.,
+            flow_speed: 0.,
+            outline_width: 0.,
+            outline_color: Vec4::zero(),
+        }
+    }
+
+    pub fn with_cap(mut self, cap: LineCap) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    /// Dash and gap lengths along the polyline, up to 4 values. Odd patterns are repeated like in SVG,
+    /// so `&[5.]` is 5 dash, 5 gap. Zero dash length with round cap gives dotted line.
+    ///
+    /// Panics if the pattern doesn't fit into 4 values after the repeat, e.g. `&[5., 2., 1.]`
+    pub fn with_dash(mut self, pattern: &[f32], offset: f32) -> Self {
+        let len = pattern.len() * (1 + pattern.len() % 2);
+        assert!(
+            len <= 4,
+            "dash pattern of {} values is {} long, only 4 are supported",
+            pattern.len(),
+            len
+        );
+        let mut dash = [0.; 4];
+        for (value, length) in dash.iter_mut().zip(pattern.iter().cycle().take(len)) {
+            *value = *length;
         }
+        self.dash = Vec4::from(dash);
+        self.dash_offset = offset;
+        self
     }
+
+    /// Casing drawn outside of the line in one pass (like roads on maps)
+    pub fn with_outline(mut self, width: f32, color: Vec4) -> Self {
+        self.outline_width = width;
+        self.outline_color = color;
+        self
+    }
+
+    /// Moves dash pattern along the polyline, uses `LinesRenderer::time`
+    pub fn with_flow(mut self, speed: f32) -> Self {
+        self.flow_speed = speed;
+        self
+    }
+}
+
+/// Stable id of a line in `Lines`, stays valid until the line is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LineHandle {
+    slot: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Slot {
+    generation: u32,
+    /// index in the dense lines array, None for free slot
+    index: Option<usize>,
 }
 
+/// Retained storage of lines. Lines are kept densely packed for drawing,
+/// handles point to them through slots, so removal doesn't invalidate other handles
 #[derive(Debug, Default, Clone)]
-pub struct Lines(Vec<Line>);
+pub struct Lines {
+    lines: Vec<Line>,
+    /// exact f64 `position` of every line in `lines`, renderer rebases lines with it
+    centers: Vec<DVec2>,
+    /// slot of every line in `lines`
+    owners: Vec<u32>,
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+    /// ranges of lines changed since the last upload to GPU
+    dirty: Vec<Range<usize>>,
+}
 
 impl Lines {
     fn new_gpu_backed(max_lines_num: usize) -> Self {
-        Lines(Vec::with_capacity(max_lines_num))
+        Lines {
+            lines: Vec::with_capacity(max_lines_num),
+            centers: Vec::with_capacity(max_lines_num),
+            owners: Vec::with_capacity(max_lines_num),
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter()
+    }
+
+    /// Line at `index` in `iter` order with its f64 position
+    pub(crate) fn line_with_center(&self, index: usize) -> (&Line, DVec2) {
+        (&self.lines[index], self.centers[index])
+    }
+
+    /// Handle of the line at `index` in `iter` order
+    pub(crate) fn handle_at(&self, index: usize) -> LineHandle {
+        let slot = self.owners[index];
+        LineHandle {
+            slot,
+            generation: self.slots[slot as usize].generation,
+        }
+    }
+
+    /// Lines changed since the last upload to GPU
+    pub(crate) fn has_changes(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Lines with their f64 positions
+    pub(crate) fn iter_with_centers(&self) -> impl Iterator<Item = (&Line, DVec2)> {
+        self.lines.iter().zip(self.centers.iter().copied())
     }
 
     pub fn clear(&mut self) {
-        self.0.clear();
+        for slot in self.owners.drain(..) {
+            self.slots[slot as usize].index = None;
+            self.slots[slot as usize].generation += 1;
+            self.free_slots.push(slot);
+        }
+        self.lines.clear();
+        self.centers.clear();
+        self.dirty.clear();
+    }
+
+    pub fn add(&mut self, line: Line) -> LineHandle {
+        self.add_at(line, line.position.into())
+    }
+
+    /// `center` is precise `line.position`
+    pub(crate) fn add_at(&mut self, line: Line, center: DVec2) -> LineHandle {
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(Slot::default());
+                self.slots.len() as u32 - 1
+            }
+        };
+        let index = self.lines.len();
+        self.slots[slot as usize].index = Some(index);
+        self.lines.push(line);
+        self.centers.push(center);
+        self.owners.push(slot);
+        self.mark_dirty(index);
+        LineHandle {
+            slot,
+            generation: self.slots[slot as usize].generation,
+        }
+    }
+
+    /// Copies lines of `segments` into new slots. Returns their handles in `segments.iter()` order,
+    /// handles of `segments` itself don't refer to these copies
+    pub fn extend(&mut self, segments: &Lines) -> Vec<LineHandle> {
+        segments
+            .iter_with_centers()
+            .map(|(line, center)| self.add_at(*line, center))
+            .collect()
     }
 
-    pub fn add(&mut self, line: Line) {
-        self.0.push(line);
+    fn index(&self, handle: LineHandle) -> Option<usize> {
+        let slot = self.slots.get(handle.slot as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.index
+    }
+
+    pub fn get(&self, handle: LineHandle) -> Option<&Line> {
+        self.index(handle).map(|index| &self.lines[index])
     }
 
-    pub fn extend(&mut self, segments: &Lines) {
-        self.0.extend(segments.0.iter())
+    /// Position of the line without f32 rounding
+    pub fn center(&self, handle: LineHandle) -> Option<DVec2> {
+        self.index(handle).map(|index| self.centers[index])
     }
+
+    /// Returns false if the line was already removed.
+    /// Precise position is kept if `line.position` isn't changed
+    pub fn update(&mut self, handle: LineHandle, line: Line) -> bool {
+        match self.index(handle) {
+            Some(index) => {
+                if line.position != self.lines[index].position {
+                    self.centers[index] = line.position.into();
+                }
+                self.lines[index] = line;
+                self.mark_dirty(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The last line takes place of the removed one. Returns removed line
+    pub fn remove(&mut self, handle: LineHandle) -> Option<Line> {
+        let index = self.index(handle)?;
+        let line = self.lines.swap_remove(index);
+        self.centers.swap_remove(index);
+        self.owners.swap_remove(index);
+        if let Some(moved) = self.owners.get(index) {
+            self.slots[*moved as usize].index = Some(index);
+            self.mark_dirty(index);
+        }
+        let slot = &mut self.slots[handle.slot as usize];
+        slot.index = None;
+        slot.generation += 1;
+        self.free_slots.push(handle.slot);
+        Some(line)
+    }
+
+    fn mark_dirty(&mut self, index: usize) {
+        // consecutive changes (appends, polylines) just extend the last range
+        if let Some(last) = self.dirty.last_mut() {
+            if last.start <= index + 1 && index <= last.end {
+                last.start = last.start.min(index);
+                last.end = last.end.max(index + 1);
+                return;
+            }
+        }
+        self.dirty.push(index..index + 1);
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty.clear();
+        self.dirty.push(0..self.lines.len());
+    }
+
+    /// Ranges of lines (in `iter` order) changed since the last call, clipped to the current length.
+    /// `LinesRenderer` takes them from its lines to upload, call it only to fill own buffers
+    pub fn take_dirty(&mut self) -> Vec<Range<usize>> {
+        let len = self.lines.len();
+        self.dirty
+            .drain(..)
+            .filter_map(|dirty| {
+                let end = dirty.end.min(len);
+                if dirty.start < end {
+                    Some(dirty.start..end)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Adds open polyline, segments are connected with joins and the ends get style caps.
+    /// Points are Vec2 or DVec2, the last keeps precision of big world coordinates
+    pub fn add_polyline<P: Copy + Into<DVec2>>(
+        &mut self,
+        points: &[P],
+        style: LineStyle,
+    ) -> Vec<LineHandle> {
+        self.add_path(points, None, false, style)
+    }
+
+    /// Adds closed ring, the last point is connected to the first one (it may be repeated or not)
+    pub fn add_polygon<P: Copy + Into<DVec2>>(
+        &mut self,
+        points: &[P],
+        style: LineStyle,
+    ) -> Vec<LineHandle> {
+        self.add_path(points, None, true, style)
+    }
+
+    /// Open polyline with a color per point, colors are interpolated along segments
+    pub fn add_gradient_polyline<P: Copy + Into<DVec2>>(
+        &mut self,
+        points: &[P],
+        colors: &[Vec4],
+        style: LineStyle,
+    ) -> Vec<LineHandle> {
+        assert_eq!(
+            points.len(),
+            colors.len(),
+            "color is needed for every point"
+        );
+        self.add_path(points, Some(colors), false, style)
+    }
+
+    /// Open polyline colored by a scalar per point (speed, elevation...) mapped through `colormap`
+    pub fn add_colormapped_polyline<P: Copy + Into<DVec2>, F: Fn(f32) -> Vec4>(
+        &mut self,
+        points: &[P],
+        values: &[f32],
+        colormap: F,
+        style: LineStyle,
+    ) -> Vec<LineHandle> {
+        let colors: Vec<Vec4> = values.iter().map(|value| colormap(*value)).collect();
+        self.add_gradient_polyline(points, &colors, style)
+    }
+
+    fn add_path<P: Copy + Into<DVec2>>(
+        &mut self,
+        points: &[P],
+        colors: Option<&[Vec4]>,
+        closed: bool,
+        style: LineStyle,
+    ) -> Vec<LineHandle> {
+        // zero length segments have no direction, so just skip repeated points
+        let mut path: Vec<DVec2> = Vec::with_capacity(points.len());
+        let mut path_colors: Vec<Vec4> = Vec::with_capacity(points.len());
+        for (i, point) in points.iter().enumerate() {
+            let point = (*point).into();
+            if path.last() != Some(&point) {
+                path.push(point);
+                path_colors.push(colors.map_or(style.color, |colors| colors[i]));
+            }
+        }
+        if closed && path.len() > 1 && path.first() == path.last() {
+            path.pop();
+            path_colors.pop();
+        }
+        let closed = closed && path.len() > 2;
+        let n = path.len();
+        let segments_num = if closed { n } else { n.saturating_sub(1) };
+        let mut distance = 0f64;
+        let mut handles = Vec::with_capacity(segments_num);
+        for i in 0..segments_num {
+            let colors = (path_colors[i], path_colors[(i + 1) % n]);
+            let (line, center) =
+                path_segment(|j| path[j], n, closed, i, colors, distance as f32, &style);
+            handles.push(self.add_at(line, center));
+            distance += (path[(i + 1) % n] - path[i]).length();
+        }
+        handles
+    }
+}
+
+/// Segment `i` of the path of `n` points without repeats with its f64 center.
+/// `colors` are at the segment ends, `distance` is along the path to the segment start
+pub(crate) fn path_segment<F: Fn(usize) -> DVec2>(
+    point: F,
+    n: usize,
+    closed: bool,
+    i: usize,
+    colors: (Vec4, Vec4),
+    distance: f32,
+    style: &LineStyle,
+) -> (Line, DVec2) {
+    let segments_num = if closed { n } else { n - 1 };
+    let from = point(i);
+    let to = point((i + 1) % n);
+    // differences are taken in f64, so directions are exact for any coordinates
+    let prev_dir = if closed || i > 0 {
+        (from - point((i + n - 1) % n)).as_vec2()
+    } else {
+        Vec2::zero()
+    };
+    let next_dir = if closed || i + 1 < segments_num {
+        (point((i + 2) % n) - to).as_vec2()
+    } else {
+        Vec2::zero()
+    };
+    let segment_type = match i {
+        0 if !closed => SegmentType::All,
+        _ => SegmentType::NoFirst,
+    };
+    let center = (from + to) / 2.;
+    let mut line = Line::new(
+        segment_type,
+        from.as_vec2(),
+        to.as_vec2(),
+        style.thickness,
+        colors.0,
+    )
+    .with_end_color(colors.1)
+    .with_neighbours(prev_dir, next_dir)
+    .with_caps(style.cap, style.cap)
+    .with_dash(style.dash, style.dash_offset)
+    .with_flow(style.flow_speed)
+    .with_outline(style.outline_width, style.outline_color)
+    .with_distance(distance);
+    line.position = center.as_vec2();
+    line.dir = (to - from).as_vec2();
+    (line, center)
 }
 
 pub struct LinesRenderer {
     pipeline: Pipeline,
-    bindings: Bindings,
+    geometry_vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    /// one binding per instance buffer of CHUNK_LINES lines
+    chunks: Vec<Bindings>,
+    /// world position which chunk lines are relative to on GPU
+    origins: Vec<DVec2>,
     pub lines: Lines,
+    pub join: LineJoin,
+    pub width_mode: WidthMode,
+    /// seconds for animated lines, better keep it small (time since start) for f32 precision
+    pub time: f32,
+    /// width of antialiasing band at the line edge in pixels, 0 for crisp edges (pixel art)
+    pub aa_width: f32,
+    /// pixels LOD lines may differ from the original ones, see `set_lod`
+    pub lod_tolerance: f32,
+    lod: Option<LodPyramid>,
+    /// level of `lod` which is in `lines` now
+    lod_level: Option<usize>,
+    /// Upload and draw only lines in the camera view, worth it when most of lines are off the screen.
+    /// Index of lines is rebuilt after every change of `lines`, so it's for static data
+    pub culling: bool,
+    index: Option<SpatialIndex>,
+    /// world rectangle (min, max) lines in GPU buffers were queried for when culling
+    culled_area: Option<(DVec2, DVec2)>,
+    /// lines in GPU buffers, all of `lines` or visible ones when culling
+    gpu_lines: usize,
+    tiles: Option<TileSet>,
+    precision: Precision,
+    uploaded_lines: usize,
     max_lines_num: usize,
 }
 
 impl LinesRenderer {
     pub fn new(ctx: &mut Context, max_lines_num: usize) -> Self {
-        let geometry_vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &RECT);
-        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &RECT_INDICES);
-        let lines_vertex_buffer = Buffer::stream(
-            ctx,
-            BufferType::VertexBuffer,
-            max_lines_num * std::mem::size_of::<Line>(),
-        );
-        let bindings = Bindings {
-            vertex_buffers: vec![geometry_vertex_buffer, lines_vertex_buffer],
+        let geometry_vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, RECT);
+        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, RECT_INDICES);
+
+        let pipeline = Self::pipeline(ctx, Precision::default());
+        let mut renderer = LinesRenderer {
+            lines: Lines::new_gpu_backed(max_lines_num),
+            pipeline,
+            geometry_vertex_buffer,
             index_buffer,
-            images: vec![],
+            chunks: vec![],
+            origins: vec![],
+            join: LineJoin::default(),
+            width_mode: WidthMode::default(),
+            time: 0.,
+            aa_width: 1.,
+            lod_tolerance: 1.,
+            lod: None,
+            lod_level: None,
+            culling: false,
+            index: None,
+            culled_area: None,
+            gpu_lines: 0,
+            tiles: None,
+            precision: Precision::default(),
+            uploaded_lines: 0,
+            max_lines_num,
         };
+        renderer.reserve(ctx, max_lines_num);
+        renderer
+    }
 
-        let shader = Shader::new(
-            ctx,
-            hex_shader::VERTEX,
-            hex_shader::FRAGMENT,
-            hex_shader::META,
-        );
-        let pipeline = Pipeline::with_params(
+    fn pipeline(ctx: &mut Context, precision: Precision) -> Pipeline {
+        let (vertex, fragment) = hex_shader::sources(precision);
+        let shader = Shader::new(ctx, &vertex, &fragment, hex_shader::META);
+        Pipeline::with_params(
             ctx,
             &[
                 BufferLayout::default(),
@@ -120,30 +728,111 @@ impl LinesRenderer {
             ],
             &[
                 VertexAttribute::with_buffer("pos", VertexFormat::Float2, 0),
-                VertexAttribute::with_buffer("segment_type", VertexFormat::Float1, 1),
-                VertexAttribute::with_buffer("inst_pos", VertexFormat::Float2, 1),
-                VertexAttribute::with_buffer("thickness", VertexFormat::Float1, 1),
-                VertexAttribute::with_buffer("dir", VertexFormat::Float2, 1),
-                VertexAttribute::with_buffer("color0", VertexFormat::Float3, 1),
+                // fields of GpuLine
+                VertexAttribute::with_buffer("segment", VertexFormat::Float4, 1),
+                VertexAttribute::with_buffer("params", VertexFormat::Float4, 1),
+                VertexAttribute::with_buffer("path", VertexFormat::Float4, 1),
+                VertexAttribute::with_buffer("color0", VertexFormat::Float4, 1),
+                VertexAttribute::with_buffer("dash", VertexFormat::Float4, 1),
+                VertexAttribute::with_buffer("color1", VertexFormat::Float4, 1),
+                VertexAttribute::with_buffer("outline_color", VertexFormat::Float4, 1),
             ],
             shader,
             PipelineParams {
+                // fragments are premultiplied: color is blended with SourceAlpha, OneMinusSourceAlpha
+                // and alpha with One, OneMinusSourceAlpha, so opaque targets stay opaque
                 color_blend: Some((
                     Equation::Add,
-                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::One,
                     BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
                 )),
                 ..Default::default()
             },
-        );
-        LinesRenderer {
-            lines: Lines::new_gpu_backed(max_lines_num),
-            pipeline,
-            bindings,
-            max_lines_num,
+        )
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Recompiles shaders. miniquad can't delete the old ones, so better call it once at start
+    pub fn set_precision(&mut self, ctx: &mut Context, precision: Precision) {
+        if precision != self.precision {
+            self.pipeline = Self::pipeline(ctx, precision);
+            self.precision = precision;
+        }
+    }
+
+    /// Number of lines that fit into allocated GPU buffers
+    pub fn capacity(&self) -> usize {
+        self.chunks.len() * CHUNK_LINES
+    }
+
+    /// Allocates instance buffers for at least `lines_num` lines
+    pub fn reserve(&mut self, ctx: &mut Context, lines_num: usize) {
+        while self.capacity() < lines_num {
+            let lines_vertex_buffer = Buffer::stream(
+                ctx,
+                BufferType::VertexBuffer,
+                CHUNK_LINES * std::mem::size_of::<GpuLine>(),
+            );
+            self.chunks.push(Bindings {
+                vertex_buffers: vec![self.geometry_vertex_buffer, lines_vertex_buffer],
+                index_buffer: self.index_buffer,
+                images: vec![],
+            });
+            self.origins.push(DVec2::zero());
+        }
+    }
+
+    /// Draws a level of the pyramid picked by camera zoom instead of lines set by hand.
+    /// `lines` is replaced with the level when it changes, so handles to `lines` don't survive it
+    pub fn set_lod(&mut self, lod: Option<LodPyramid>) {
+        self.lod = lod;
+        self.lod_level = None;
+    }
+
+    pub fn lod(&self) -> Option<&LodPyramid> {
+        self.lod.as_ref()
+    }
+
+    /// Level of the pyramid drawn the last time
+    pub fn lod_level(&self) -> Option<usize> {
+        self.lod_level
+    }
+
+    /// Puts LOD level for the camera into `lines`, whole level is uploaded when it changes
+    pub(crate) fn update_lod(&mut self, camera: &Camera, width: f32) {
+        let lod = match &self.lod {
+            Some(lod) => lod,
+            None => return,
+        };
+        let level = lod.level_for(camera.zoom, width, self.lod_tolerance);
+        if self.lod_level != Some(level) {
+            self.lines.clear();
+            for (line, center) in lod.lines(level) {
+                self.lines.add_at(line, center);
+            }
+            self.lod_level = Some(level);
         }
     }
 
+    /// Draws tiles of a big static dataset, they have their own GPU buffers.
+    /// `lines` are drawn too, on top of the tiles, so the renderer for tiles may be created small.
+    /// GPU buffers of the previous tiles are deleted
+    pub fn set_tiles(&mut self, tiles: Option<TileSet>) {
+        self.tiles = tiles;
+    }
+
+    pub fn tiles(&self) -> Option<&TileSet> {
+        self.tiles.as_ref()
+    }
+
+    pub fn tiles_mut(&mut self) -> Option<&mut TileSet> {
+        self.tiles.as_mut()
+    }
+
+    /// Lines to fill and pass to `push_segments`, use the handles it returns to change them later
     pub fn create_lines(&self) -> Lines {
         Lines::new_gpu_backed(self.max_lines_num)
     }
@@ -152,74 +841,356 @@ impl LinesRenderer {
         self.lines.clear();
     }
 
-    /// WARNING: for GPU safety it's important that Lines are created with correct size (new_gpu_backed fn)
-    pub fn push_segments(&mut self, ctx: &mut Context, lines: Lines) {
-        self.lines.extend(&lines);
-        self.bindings.vertex_buffers[1].update(ctx, &self.lines.0[..]);
+    /// Appends lines, buffers grow if there is not enough capacity.
+    /// Returns handles of the lines in `self.lines`, in `lines.iter()` order
+    pub fn push_segments(&mut self, ctx: &mut Context, lines: Lines) -> Vec<LineHandle> {
+        let handles = self.lines.extend(&lines);
+        self.sync(ctx);
+        handles
+    }
+
+    /// Uploads lines changed through `self.lines` since the last sync, `draw` calls it too.
+    /// Only chunks with changes are uploaded, so the cost depends on what changed, not on the lines number.
+    /// Positions are uploaded relative to the chunk origin (its first line), so they stay small for f32.
+    /// With culling lines are uploaded by `draw`, cause it knows the view
+    pub fn sync(&mut self, ctx: &mut Context) {
+        if self.culling {
+            return;
+        }
+        if self.culled_area.take().is_some() {
+            // buffers have only lines visible the last time
+            self.lines.mark_all_dirty();
+        }
+        // changes of lines aren't tracked without culling
+        self.index = None;
+        self.gpu_lines = self.lines.len();
+        self.reserve(ctx, self.lines.len());
+        // miniquad updates buffer from its start, so chunk is uploaded up to its last changed line
+        let mut upload_ends: BTreeMap<usize, usize> = BTreeMap::new();
+        for dirty in self.lines.take_dirty() {
+            for chunk in dirty.start / CHUNK_LINES..dirty.end.div_ceil(CHUNK_LINES) {
+                let end = dirty.end.min((chunk + 1) * CHUNK_LINES);
+                let upload_end = upload_ends.entry(chunk).or_insert(end);
+                *upload_end = (*upload_end).max(end);
+            }
+        }
+        self.uploaded_lines = 0;
+        let mut staging = Vec::with_capacity(CHUNK_LINES);
+        for (chunk, mut end) in upload_ends {
+            let origin = self.lines.centers[chunk * CHUNK_LINES];
+            if origin != self.origins[chunk] {
+                // all lines of the chunk are relative to the origin
+                self.origins[chunk] = origin;
+                end = self.chunk_range(chunk).end;
+            }
+            let range = chunk * CHUNK_LINES..end;
+            self.uploaded_lines += range.len();
+            staging.clear();
+            for i in range {
+                let position = (self.lines.centers[i] - origin).as_vec2();
+                staging.push(GpuLine::new(&self.lines.lines[i], position));
+            }
+            self.chunks[chunk].vertex_buffers[1].update(ctx, &staging);
+        }
+    }
+
+    /// Uploads lines around the camera view. Lines are queried for the view extended by its half
+    /// on every side, so they are uploaded again only when the view leaves that area or gets much smaller
+    fn sync_visible(&mut self, ctx: &mut Context, camera: &Camera, width: f32, height: f32) {
+        let outdated = match &self.index {
+            Some(index) => !index.is_for(self.width_mode, self.join),
+            None => true,
+        };
+        if !self.lines.take_dirty().is_empty() || outdated {
+            self.index = Some(SpatialIndex::new(&self.lines, self.width_mode, self.join));
+            self.culled_area = None;
+        }
+        let index = self.index.as_ref().unwrap();
+        let (min, max) = index.view_bounds(camera, width, height);
+        let size = max - min;
+        self.uploaded_lines = 0;
+        if let Some((area_min, area_max)) = self.culled_area {
+            let inside = area_min.x <= min.x
+                && area_min.y <= min.y
+                && max.x <= area_max.x
+                && max.y <= area_max.y;
+            if inside && (area_max.x - area_min.x) < size.x * 4. {
+                return;
+            }
+        }
+        let area = (min - size / 2., max + size / 2.);
+        let visible = index.query(area.0, area.1);
+        self.culled_area = Some(area);
+        self.reserve(ctx, visible.len());
+
+        let mut staging = Vec::with_capacity(CHUNK_LINES);
+        for (chunk, indices) in visible.chunks(CHUNK_LINES).enumerate() {
+            let origin = self.lines.centers[indices[0]];
+            self.origins[chunk] = origin;
+            staging.clear();
+            for i in indices {
+                let position = (self.lines.centers[*i] - origin).as_vec2();
+                staging.push(GpuLine::new(&self.lines.lines[*i], position));
+            }
+            self.chunks[chunk].vertex_buffers[1].update(ctx, &staging);
+        }
+        self.uploaded_lines = visible.len();
+        self.gpu_lines = visible.len();
+    }
+
+    /// Lines and tile lines within `tolerance_px` pixels of `world_point` as they are drawn
+    /// by the camera on the screen of width x height pixels, the closest first (see `pick::pick`).
+    /// Lines are drawn over tiles, so they go first at the same distance.
+    /// With culling the spatial index finds candidates, tiles are searched with their index
+    /// of level 0 (see `TileSet::pick`)
+    pub fn pick<P: Into<DVec2>>(
+        &mut self,
+        camera: &Camera,
+        width: f32,
+        height: f32,
+        world_point: P,
+        tolerance_px: f32,
+    ) -> Vec<Picked> {
+        let point = world_point.into();
+        let raster = Raster::from_renderer(self);
+        let lines = &self.lines;
+        let candidates = match &self.index {
+            // index isn't rebuilt until the next draw
+            Some(index) if self.culling && !lines.has_changes() => {
+                index.near(point, camera, width, tolerance_px)
+            }
+            _ => (0..lines.len()).collect(),
+        };
+        let mut hits: Vec<(f32, Picked)> = pick_from(
+            |i| line_at(lines, i),
+            candidates,
+            &raster,
+            camera,
+            width,
+            height,
+            point,
+            tolerance_px,
+        )
+        .into_iter()
+        .map(|(distance, i)| (distance, Picked::Line(lines.handle_at(i))))
+        .collect();
+        if let Some(tiles) = &mut self.tiles {
+            let tile_hits = tiles.pick_hits(&raster, camera, width, height, point, tolerance_px);
+            hits.extend(
+                tile_hits
+                    .into_iter()
+                    .map(|(distance, i)| (distance, Picked::Tile(i))),
+            );
+            // stable, so lines stay before tiles
+            hits.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        hits.into_iter().map(|(_, picked)| picked).collect()
+    }
+
+    /// Number of lines sent to GPU by the last sync
+    pub fn uploaded_lines(&self) -> usize {
+        self.uploaded_lines
+    }
+
+    /// Number of lines drawn, less than `lines.len()` with culling
+    pub fn drawn_lines(&self) -> usize {
+        self.gpu_lines
+    }
+
+    fn chunks_used(&self) -> usize {
+        self.gpu_lines.div_ceil(CHUNK_LINES)
+    }
+
+    fn chunk_range(&self, chunk: usize) -> std::ops::Range<usize> {
+        chunk * CHUNK_LINES..self.gpu_lines.min((chunk + 1) * CHUNK_LINES)
     }
 
     pub fn draw(&mut self, ctx: &mut Context, camera: &Camera) {
         let (width, height) = ctx.screen_size();
-        let mvp = camera.get_mvp(height / width);
+        self.draw_to(ctx, camera, width, height);
+    }
+
+    /// Draws into the current pass of width x height pixels, e.g. offscreen one
+    pub fn draw_to(&mut self, ctx: &mut Context, camera: &Camera, width: f32, height: f32) {
+        self.update_lod(camera, width);
+        if self.culling {
+            self.sync_visible(ctx, camera, width, height);
+        } else {
+            self.sync(ctx);
+        }
+        // everything is drawn relative to the camera eye, big world coordinates never reach GPU
+        // (bindings, origin, instances)
+        let mut draws = match &mut self.tiles {
+            Some(tiles) => {
+                tiles.set_width_mode(self.width_mode);
+                tiles.set_join(self.join);
+                let geometry = (self.geometry_vertex_buffer, self.index_buffer);
+                tiles.prepare(ctx, camera, width, height, self.lod_tolerance, geometry)
+            }
+            None => vec![],
+        };
+        for chunk in 0..self.chunks_used() {
+            let instances = self.chunk_range(chunk).len();
+            draws.push((self.chunks[chunk].clone(), self.origins[chunk], instances));
+        }
+
+        let mvp = camera.get_projection(height / width);
+        let (join, miter_limit) = self.join.as_uniform();
 
         ctx.apply_pipeline(&self.pipeline);
-        ctx.apply_bindings(&self.bindings);
-        ctx.apply_uniforms(&hex_shader::Uniforms { mvp });
-        ctx.draw(0, RECT_INDICES.len() as i32, self.lines.0.len() as i32);
+        for (bindings, origin, instances) in draws {
+            ctx.apply_uniforms(&hex_shader::Uniforms {
+                mvp,
+                join,
+                miter_limit,
+                viewport: vec2(width, height),
+                width_mode: match self.width_mode {
+                    WidthMode::World => 0.,
+                    WidthMode::Pixels => 1.,
+                },
+                time: self.time,
+                offset: (origin - camera.position2d).as_vec2(),
+                aa_width: self.aa_width,
+            });
+            ctx.apply_bindings(&bindings);
+            ctx.draw(0, RECT_INDICES.len() as i32, instances as i32);
+        }
     }
 }
 
 mod hex_shader {
     use miniquad::*;
 
-    pub const VERTEX: &str = r#"#version 100
-    precision lowp float;
+    use super::Precision;
+
+    /// highp is optional only in fragment shaders
+    const FRAGMENT_HIGHP: &str = concat!(
+        "#version 100\n",
+        "#ifdef GL_FRAGMENT_PRECISION_HIGH\n",
+        "precision highp float;\n",
+        "#else\n",
+        "precision mediump float;\n",
+        "#endif\n",
+    );
+
+    /// (vertex, fragment) sources with precision header
+    pub fn sources(precision: Precision) -> (String, String) {
+        let header = |fragment: bool| match precision {
+            Precision::Low => "#version 100\nprecision lowp float;\n",
+            Precision::Medium => "#version 100\nprecision mediump float;\n",
+            Precision::High if fragment => FRAGMENT_HIGHP,
+            Precision::High => "#version 100\nprecision highp float;\n",
+        };
+        (
+            format!("{}{}", header(false), VERTEX),
+            format!("{}{}", header(true), FRAGMENT),
+        )
+    }
+
+    pub const VERTEX: &str = r#"
     attribute vec2 pos;
-    attribute float segment_type;
-    attribute vec2 inst_pos;
-    attribute float thickness;
-    attribute vec2 dir;
-    attribute vec3 color0;
-
-    varying vec2 local_position;
-    varying vec2 projected_position;
-    varying vec2 ip;
-    varying float th;
+    // fields of GpuLine
+    // position and dir
+    attribute vec4 segment;
+    // segment type + 4 * start cap + 16 * end cap, thickness, outline width, distance
+    attribute highp vec4 params;
+    // dash offset, flow speed, angles of prev and next dir (8 without neighbour)
+    attribute highp vec4 path;
+    attribute vec4 color0;
+    attribute vec4 dash;
+    attribute vec4 color1;
+    attribute vec4 outline_color;
+
+    // position relative to the segment start, dir
+    varying vec4 seg;
+    // thickness, outline width, segment type (have to pass as float, but it is just enum)
+    // and position of the segment start in the dash pattern
+    varying vec4 prm;
+    varying vec4 oc;
     varying vec4 color;
-    // segment type. Have to pass as float, but it is just enum
-    varying float st;
-    varying vec2 dr;
+    varying vec4 end_color;
+    varying vec4 nb;
+    varying vec2 cp;
+    varying vec4 ds;
 
     uniform mat4 mvp;
+    uniform float join;
+    uniform float miter_limit;
+    uniform vec2 viewport;
+    uniform float width_mode;
+    uniform float time;
+    // origin of the chunk positions relative to the camera eye
+    uniform vec2 offset;
     void main() {
-        vec2 n = vec2(-dir.y, dir.x) / length(dir);
-        vec2 apos = pos.y * dir + pos.x * n * thickness;
-        vec4 new_pos = vec4(apos + inst_pos, 0.0, 1.0);
+        // world units in one unit of thickness
+        float scale = 1.;
+        if (width_mode > 0.5) {
+            // clip space is 2 units high
+            scale = 2. / (mvp[1][1] * viewport.y);
+        }
+        vec2 inst_pos = segment.xy;
+        vec2 dir = segment.zw;
+        float th = params.y * scale;
+        float ow = params.z * scale;
+        vec2 t = dir / length(dir);
+        vec2 n = vec2(-t.y, t.x);
+        // how far caps and joins stick out of the segment ends
+        float ext = th + ow;
+        if (join < 0.5) {
+            ext *= max(miter_limit, 1.);
+        }
+        vec2 apos = pos.y * (dir / 2. + t * ext) + pos.x * n * (th + ow);
+        vec4 new_pos = vec4(apos + inst_pos + offset, 0.0, 1.0);
         vec4 res_pos = mvp * new_pos;
         gl_Position = res_pos;
 
-        st = segment_type;
-        local_position = pos;
-        projected_position = vec2(new_pos.x, new_pos.y);
-        ip = inst_pos;
-        dr = dir;
-        th = thickness;
-        color = vec4(color0, 0.5);
+        seg = vec4(apos + dir / 2., dir);
+        highp float kind = params.x;
+        // moving dashes forward is the same as moving pattern start back
+        prm = vec4(th, ow, mod(kind, 4.), params.w + (path.x - path.y * time) * scale);
+        cp = vec2(mod(floor(kind / 4.), 4.), floor(kind / 16.));
+        nb = vec4(0.);
+        if (path.z < 4.) {
+            nb.xy = vec2(cos(path.z), sin(path.z));
+        }
+        if (path.w < 4.) {
+            nb.zw = vec2(cos(path.w), sin(path.w));
+        }
+        ds = dash * scale;
+        color = color0;
+        end_color = color1;
+        oc = outline_color;
     }
     "#;
 
-    pub const FRAGMENT: &str = r#"#version 100
-    precision lowp float;
-    varying vec2 local_position;
-    varying vec2 projected_position;
-    varying vec2 ip;
-    varying float th;
+    pub const FRAGMENT: &str = r#"
+    varying vec4 seg;
+    varying vec4 prm;
+    varying vec4 oc;
     varying vec4 color;
-    varying float st;
-    varying vec2 dr;
+    varying vec4 end_color;
+    varying vec4 nb;
+    varying vec2 cp;
+    varying vec4 ds;
+
+    // thickness, set from prm in main
+    float th;
 
     uniform mat4 mvp;
-    const lowp float aaborder = 0.00445;
+    uniform float join;
+    uniform float miter_limit;
+    uniform vec2 viewport;
+    uniform float width_mode;
+    uniform float time;
+    uniform float aa_width;
+
+    // 1 inside the shape, 0 outside, smooth in the band of width `border` inside the edge
+    float coverage(in float d, in float border) {
+        if (border <= 0.) {
+            return 1. - step(0., d);
+        }
+        return 1. - smoothstep(-border, 0., d);
+    }
 
     float line_segment(in vec2 p, in vec2 a, in vec2 b) {
         vec2 ba = b - a;
@@ -228,29 +1199,134 @@ mod hex_shader {
         return length(pa - h * ba);
     }
 
+    // distance to the join shape for points behind the segment end `e`.
+    // t_out -- direction from the segment to its end, w -- direction of the neighbour from `e`
+    float join_distance(in vec2 p, in vec2 e, in vec2 t_out, in vec2 w) {
+        vec2 n = vec2(-t_out.y, t_out.x);
+        float across = abs(dot(p - e, n)) - th;
+        if (join > 0.5 && join < 1.5) {
+            return length(p - e) - th;
+        }
+        vec2 m = t_out - w;
+        if (dot(m, m) < 0.0001) {
+            return across;
+        }
+        // outer bisector of the corner
+        m = normalize(m);
+        float sin_half = abs(dot(n, m));
+        if (join < 0.5 && sin_half * miter_limit >= 1.) {
+            return across;
+        }
+        return max(across, dot(p - e, m) - th * sin_half);
+    }
+
+    // distance to butt and square caps, round ones are given by line_segment
+    float cap_distance(in vec2 p, in vec2 e, in vec2 t_out, in float cap) {
+        vec2 n = vec2(-t_out.y, t_out.x);
+        float across = abs(dot(p - e, n)) - th;
+        float along = dot(p - e, t_out);
+        if (cap < 0.5) {
+            return max(across, along);
+        }
+        return max(across, along - th);
+    }
+
+    // distance to the dash [l, r], q -- position along the path, c -- distance to the center line
+    float dash_distance(in float q, in float l, in float r, in float c) {
+        float along = max(l - q, q - r);
+        if (cp.x < 0.5) {
+            return max(c - th, along);
+        } else if (cp.x < 1.5) {
+            return max(c - th, along - th);
+        }
+        return length(vec2(max(along, 0.), c)) - th;
+    }
+
+    // the dash [l, r] in this period and in the neighbour ones, caps stick out over the period ends
+    float periodic_dash(in float q, in float l, in float r, in float c, in float total) {
+        float d = min(dash_distance(q, l, r, c), dash_distance(q, l + total, r + total, c));
+        return min(d, dash_distance(q, l - total, r - total, c));
+    }
+
+    float dashes(in float q, in float c) {
+        float total = ds.x + ds.y + ds.z + ds.w;
+        q = mod(q, total);
+        float d = periodic_dash(q, 0., ds.x, c, total);
+        if (ds.z > 0.) {
+            float l = ds.x + ds.y;
+            d = min(d, periodic_dash(q, l, l + ds.z, c, total));
+        }
+        return d;
+    }
+
     void main() {
-        vec2 a = ip - dr  / 2.;
-        vec2 b = ip + dr / 2.;
-        float d = line_segment(projected_position, a, b) - th;
-        float scaled_border = aaborder / mvp[1][1];
-        float edge1 = -scaled_border;
-        float edge2 = 0.;
-
-        if (d < 0.) {
-            float smooth = 1.;
-            if (abs(st - 1.) < 0.01 && local_position.y < -0.5) { // in SDF space
+        th = prm.x;
+        float ow = prm.y;
+        float st = prm.z;
+        vec2 p = seg.xy;
+        vec2 a = vec2(0., 0.);
+        vec2 dr = seg.zw;
+        vec2 b = dr;
+        vec2 t = normalize(dr);
+        // position along the segment, 0 at the start and 1 at the end
+        float h = dot(p - a, dr) / dot(dr, dr);
+        float d = line_segment(p, a, b) - th;
+
+        // segments split the corner by bisector, so joins never overlap
+        bool start_join = dot(nb.xy, nb.xy) > 0.;
+        bool end_join = dot(nb.zw, nb.zw) > 0.;
+        if (start_join) {
+            vec2 w = -normalize(nb.xy);
+            if (dot(p - a, t - w) < 0.) {
                 discard;
-            } else if (abs(st - 2.) < 0.01 && local_position.y > 0.5) {
+            }
+            if (h < 0.) {
+                d = join_distance(p, a, -t, w);
+            }
+        } else if (h < 0. && cp.x < 1.5) {
+            d = cap_distance(p, a, -t, cp.x);
+        }
+        if (end_join) {
+            vec2 w = normalize(nb.zw);
+            if (dot(p - b, -t - w) < 0.) {
                 discard;
             }
-            if (d > edge1) {
-                smooth = 1. - smoothstep(edge1, edge2, d) + st - st;
+            if (h > 1.) {
+                d = join_distance(p, b, t, w);
             }
-            vec4 color = color;
-            color.a = smooth;
-            gl_FragColor = color;
+        } else if (h > 1. && cp.y < 1.5) {
+            d = cap_distance(p, b, t, cp.y);
+        }
+
+        if (ds.x + ds.y + ds.z + ds.w > 0.) {
+            vec2 n = vec2(-t.y, t.x);
+            float q = prm.w + dot(p - a, t);
+            d = max(d, dashes(q, abs(dot(p - a, n))));
+        }
+
+        // aa_width in world units, clip space is 2 units high
+        float border = aa_width * 2. / (mvp[1][1] * viewport.y);
+
+        // casing is outside of the line
+        float outer = d - ow;
+        if (outer < 0.) {
+            bool no_first = abs(st - 1.) < 0.01 || abs(st - 3.) < 0.01;
+            bool no_second = abs(st - 2.) < 0.01 || abs(st - 3.) < 0.01;
+            if (!start_join && no_first && h < 0.) {
+                discard;
+            } else if (!end_join && no_second && h > 1.) {
+                discard;
+            }
+            float smooth = coverage(outer, border) + st - st;
+            vec4 color = mix(color, end_color, clamp(h, 0., 1.));
+            if (ow > 0.) {
+                color = mix(oc, color, coverage(d, border));
+            }
+            color.a *= smooth;
+            // premultiplied, so the target alpha is blended with One, OneMinusSrcAlpha too
+            gl_FragColor = vec4(color.rgb * color.a, color.a);
         } else {
-            gl_FragColor = vec4(color.xyz, 0.0);
+            gl_FragColor = vec4(0.);
         }
     }
     "#;
@@ -258,12 +1334,28 @@ mod hex_shader {
     pub const META: ShaderMeta = ShaderMeta {
         images: &[],
         uniforms: UniformBlockLayout {
-            uniforms: &[("mvp", UniformType::Mat4)],
+            uniforms: &[
+                ("mvp", UniformType::Mat4),
+                ("join", UniformType::Float1),
+                ("miter_limit", UniformType::Float1),
+                ("viewport", UniformType::Float2),
+                ("width_mode", UniformType::Float1),
+                ("time", UniformType::Float1),
+                ("offset", UniformType::Float2),
+                ("aa_width", UniformType::Float1),
+            ],
         },
     };
 
     #[repr(C)]
     pub struct Uniforms {
         pub mvp: glam::Mat4,
+        pub join: f32,
+        pub miter_limit: f32,
+        pub viewport: glam::Vec2,
+        pub width_mode: f32,
+        pub time: f32,
+        pub offset: glam::Vec2,
+        pub aa_width: f32,
     }
 }
@@ -0,0 +1,98 @@
+// f64 2D vector for world positions which don't fit into f32 (e.g. Web Mercator meters).
+// glam 0.8 has only f32 types, so here is the small part needed to keep positions on CPU
+// and rebase them before they go to GPU.
+
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+
+use glam::{vec2, Vec2};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DVec2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+pub fn dvec2(x: f64, y: f64) -> DVec2 {
+    DVec2 { x, y }
+}
+
+impl DVec2 {
+    pub fn zero() -> Self {
+        DVec2 { x: 0., y: 0. }
+    }
+
+    /// Loses precision for big values, use it for differences of close positions
+    pub fn as_vec2(self) -> Vec2 {
+        vec2(self.x as f32, self.y as f32)
+    }
+
+    pub fn length(self) -> f64 {
+        self.x.hypot(self.y)
+    }
+
+    pub fn min(self, other: DVec2) -> Self {
+        dvec2(self.x.min(other.x), self.y.min(other.y))
+    }
+
+    pub fn max(self, other: DVec2) -> Self {
+        dvec2(self.x.max(other.x), self.y.max(other.y))
+    }
+}
+
+impl From<Vec2> for DVec2 {
+    fn from(v: Vec2) -> Self {
+        dvec2(v.x() as f64, v.y() as f64)
+    }
+}
+
+impl From<(f64, f64)> for DVec2 {
+    fn from((x, y): (f64, f64)) -> Self {
+        dvec2(x, y)
+    }
+}
+
+impl From<[f64; 2]> for DVec2 {
+    fn from([x, y]: [f64; 2]) -> Self {
+        dvec2(x, y)
+    }
+}
+
+impl Add for DVec2 {
+    type Output = DVec2;
+    fn add(self, other: DVec2) -> DVec2 {
+        dvec2(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for DVec2 {
+    fn add_assign(&mut self, other: DVec2) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for DVec2 {
+    type Output = DVec2;
+    fn sub(self, other: DVec2) -> DVec2 {
+        dvec2(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl SubAssign for DVec2 {
+    fn sub_assign(&mut self, other: DVec2) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<f64> for DVec2 {
+    type Output = DVec2;
+    fn mul(self, k: f64) -> DVec2 {
+        dvec2(self.x * k, self.y * k)
+    }
+}
+
+impl Div<f64> for DVec2 {
+    type Output = DVec2;
+    fn div(self, k: f64) -> DVec2 {
+        dvec2(self.x / k, self.y / k)
+    }
+}
@@ -1,2 +1,14 @@
 pub mod camera;
+pub mod colormap;
 pub mod draw_lines;
+pub mod dvec2;
+pub mod lod;
+pub mod pdf;
+pub mod pick;
+pub mod raster;
+pub mod screenshot;
+pub mod simplify;
+pub mod spatial;
+pub mod svg;
+pub mod tiles;
+pub mod vector;
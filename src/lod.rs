@@ -0,0 +1,264 @@
+// Level of detail for big datasets: polylines are simplified once for a number of levels,
+// LinesRenderer draws the coarsest level which looks the same at the current zoom.
+// Points are stored once, a level is the list of points it keeps, and its lines are made
+// when they are needed (uploaded to GPU, put into tiles), so levels don't copy the lines.
+
+use crate::draw_lines::{path_segment, Line, LineStyle, Lines};
+use crate::dvec2::DVec2;
+use crate::simplify;
+
+/// Source polyline
+struct Path {
+    closed: bool,
+    style: LineStyle,
+}
+
+/// Polyline in a level
+struct LevelPath {
+    /// index in `LodPyramid::paths`
+    path: u32,
+    /// the first point of the polyline in `LodLevel::points`
+    start: u32,
+    /// index of the first line of the polyline in the level
+    first_line: u32,
+}
+
+pub struct LodLevel {
+    /// how far lines of the level may go from the original ones, in world units
+    pub tolerance: f64,
+    /// indices of the pyramid points kept in the level, polyline after polyline
+    points: Vec<u32>,
+    paths: Vec<LevelPath>,
+    lines_num: usize,
+}
+
+impl LodLevel {
+    /// Number of lines (segments) in the level
+    pub fn len(&self) -> usize {
+        self.lines_num
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines_num == 0
+    }
+
+    fn add(&mut self, path: usize, points: impl Iterator<Item = u32>, closed: bool) {
+        let start = self.points.len();
+        self.points.extend(points);
+        let n = self.points.len() - start;
+        self.paths.push(LevelPath {
+            path: path as u32,
+            start: start as u32,
+            first_line: self.lines_num as u32,
+        });
+        self.lines_num += if closed && n > 2 { n } else { n - 1 };
+    }
+
+    /// (points of the polyline `i`, number of its lines)
+    fn path(&self, i: usize) -> (&[u32], usize) {
+        let (start, first_line) = (self.paths[i].start, self.paths[i].first_line);
+        let (end, next_line) = match self.paths.get(i + 1) {
+            Some(next) => (next.start as usize, next.first_line as usize),
+            None => (self.points.len(), self.lines_num),
+        };
+        (
+            &self.points[start as usize..end],
+            next_line - first_line as usize,
+        )
+    }
+}
+
+/// Level 0 has the original polylines, every next one is simplified with twice the tolerance
+/// of the previous one. Polylines smaller than the tolerance are dropped from the level.
+///
+/// Memory is 24 bytes per point (position, distance along the polyline, index in level 0),
+/// 4 bytes per point of every coarser level which keeps it, 72 bytes per polyline and 12 more
+/// for every level which has it. Lines (128 bytes per segment) are made only when they are needed
+pub struct LodPyramid {
+    /// points of all polylines without repeats, polyline after polyline
+    points: Vec<DVec2>,
+    /// distance along the original polyline to every point, dashes stay in place on all levels
+    distances: Vec<f32>,
+    paths: Vec<Path>,
+    levels: Vec<LodLevel>,
+}
+
+/// point of a polyline with its index in `LodPyramid::points` to simplify
+#[derive(Clone, Copy)]
+struct Indexed(DVec2, u32);
+
+impl From<Indexed> for DVec2 {
+    fn from(point: Indexed) -> Self {
+        point.0
+    }
+}
+
+impl LodPyramid {
+    /// `tolerance` of level 1 in world units
+    pub fn new(tolerance: f32, levels_num: usize) -> Self {
+        let levels = (0..levels_num.max(1))
+            .map(|i| LodLevel {
+                tolerance: match i {
+                    0 => 0.,
+                    i => tolerance as f64 * 2f64.powi(i as i32 - 1),
+                },
+                points: vec![],
+                paths: vec![],
+                lines_num: 0,
+            })
+            .collect();
+        LodPyramid {
+            points: vec![],
+            distances: vec![],
+            paths: vec![],
+            levels,
+        }
+    }
+
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    pub fn add_polyline<P: Copy + Into<DVec2>>(&mut self, points: &[P], style: LineStyle) {
+        self.add_path(points, false, style);
+    }
+
+    pub fn add_polygon<P: Copy + Into<DVec2>>(&mut self, points: &[P], style: LineStyle) {
+        self.add_path(points, true, style);
+    }
+
+    fn add_path<P: Copy + Into<DVec2>>(&mut self, points: &[P], closed: bool, style: LineStyle) {
+        // repeated points are skipped like in `Lines::add_polyline`
+        let first = self.points.len();
+        for point in points {
+            let point = (*point).into();
+            if self.points.len() == first || self.points.last() != Some(&point) {
+                self.points.push(point);
+            }
+        }
+        if closed
+            && self.points.len() - first > 1
+            && self.points[first] == self.points[self.points.len() - 1]
+        {
+            self.points.pop();
+        }
+        let points = &self.points[first..];
+        if points.len() < 2 {
+            self.points.truncate(first);
+            return;
+        }
+        let mut distance = 0f64;
+        for (i, point) in points.iter().enumerate() {
+            self.distances.push(distance as f32);
+            if let Some(next) = points.get(i + 1) {
+                distance += (*next - *point).length();
+            }
+        }
+
+        let path = self.paths.len();
+        self.paths.push(Path { closed, style });
+        let size = extent(points);
+        let indexed: Vec<Indexed> = points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| Indexed(*point, (first + i) as u32))
+            .collect();
+        for level in self.levels.iter_mut() {
+            if level.tolerance == 0. {
+                level.add(path, indexed.iter().map(|point| point.1), closed);
+            } else if size >= level.tolerance {
+                // original points every time, so the error doesn't add up through the levels
+                let tolerance = simplify::Tolerance::World(level.tolerance as f32);
+                let kept = simplify::douglas_peucker(&indexed, tolerance);
+                level.add(path, kept.iter().map(|point| point.1), closed);
+            }
+        }
+    }
+
+    /// Line `index` of the `level` with its f64 position, the same as in `level_lines`
+    pub fn line(&self, level: usize, index: usize) -> (Line, DVec2) {
+        let level = &self.levels[level];
+        let path = level
+            .paths
+            .partition_point(|path| path.first_line as usize <= index)
+            - 1;
+        self.segment(level, path, index - level.paths[path].first_line as usize)
+    }
+
+    /// Lines of the `level` with their f64 positions
+    pub fn lines(&self, level: usize) -> impl Iterator<Item = (Line, DVec2)> + '_ {
+        let level = &self.levels[level];
+        (0..level.paths.len()).flat_map(move |path| {
+            let (_, lines_num) = level.path(path);
+            (0..lines_num).map(move |i| self.segment(level, path, i))
+        })
+    }
+
+    /// Copy of the `level` in `Lines`, e.g. for export or a renderer without LOD
+    pub fn level_lines(&self, level: usize) -> Lines {
+        let mut lines = Lines::default();
+        for (line, center) in self.lines(level) {
+            lines.add_at(line, center);
+        }
+        lines
+    }
+
+    /// Line `i` of the polyline `path` of the level
+    fn segment(&self, level: &LodLevel, path: usize, i: usize) -> (Line, DVec2) {
+        let (points, _) = level.path(path);
+        let source = &self.paths[level.paths[path].path as usize];
+        let closed = source.closed && points.len() > 2;
+        let color = source.style.color;
+        path_segment(
+            |j| self.points[points[j] as usize],
+            points.len(),
+            closed,
+            i,
+            (color, color),
+            self.distances[points[i] as usize],
+            &source.style,
+        )
+    }
+
+    /// (min, max) corners of all points, None without polylines
+    pub fn bounds(&self) -> Option<(DVec2, DVec2)> {
+        let first = *self.points.first()?;
+        Some(
+            self.points
+                .iter()
+                .fold((first, first), |(min, max), p| (min.min(*p), max.max(*p))),
+        )
+    }
+
+    /// Coarsest level which differs from the original by at most `tolerance_px` pixels
+    /// on the screen of `screen_width` pixels at camera `zoom`
+    pub fn level_for(&self, zoom: f32, screen_width: f32, tolerance_px: f32) -> usize {
+        let tolerance = simplify::Tolerance::Pixels {
+            pixels: tolerance_px,
+            zoom,
+            screen_width,
+        }
+        .world();
+        self.level_for_tolerance(tolerance)
+    }
+
+    /// Coarsest level with tolerance up to `tolerance` world units
+    pub fn level_for_tolerance(&self, tolerance: f64) -> usize {
+        self.levels
+            .iter()
+            .rposition(|level| level.tolerance <= tolerance)
+            .unwrap_or(0)
+    }
+}
+
+/// the biggest side of the bounding box
+fn extent(points: &[DVec2]) -> f64 {
+    let first = match points.first() {
+        Some(first) => *first,
+        None => return 0.,
+    };
+    let (min, max) = points
+        .iter()
+        .fold((first, first), |(min, max), p| (min.min(*p), max.max(*p)));
+    (max.x - min.x).max(max.y - min.y)
+}
@@ -0,0 +1,360 @@
+// PDF export of lines, one view per page. Written by hand (PDF 1.4, no compression),
+// so it doesn't need any external tools or crates.
+// Lines are rebased on the view corner in f64 and converted to page coordinates
+// in points (1/72 inch) before writing, so big world coordinates don't lose precision.
+
+use std::{fmt::Write, fs, io, path::Path};
+
+use glam::{vec2, Vec2, Vec4};
+
+use crate::draw_lines::{LineCap, LineJoin, Lines, LinesRenderer, WidthMode};
+use crate::dvec2::DVec2;
+use crate::vector::{strokes, JoinShape, Stroke};
+
+/// Page size and margins in points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Page {
+    pub width: f32,
+    pub height: f32,
+    pub margin: f32,
+}
+
+impl Page {
+    pub const A4: Page = Page {
+        width: 595.28,
+        height: 841.89,
+        margin: 36.,
+    };
+    pub const A3: Page = Page {
+        width: 841.89,
+        height: 1190.55,
+        margin: 36.,
+    };
+    pub const LETTER: Page = Page {
+        width: 612.,
+        height: 792.,
+        margin: 36.,
+    };
+
+    pub fn landscape(self) -> Self {
+        Page {
+            width: self.width.max(self.height),
+            height: self.width.min(self.height),
+            ..self
+        }
+    }
+
+    pub fn with_margin(self, margin: f32) -> Self {
+        Page { margin, ..self }
+    }
+}
+
+/// Multi-page document, LinesRenderer settings which change the picture are applied to every page.
+/// With WidthMode::Pixels thickness is in points
+#[derive(Debug, Default, Clone)]
+pub struct Pdf {
+    pub join: LineJoin,
+    pub width_mode: WidthMode,
+    pub time: f32,
+    pages: Vec<(Page, String)>,
+    /// alpha values used in pages, every one gets ExtGState /GS<index>
+    opacities: Vec<f32>,
+}
+
+impl Pdf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_renderer(renderer: &LinesRenderer) -> Self {
+        Pdf {
+            join: renderer.join,
+            width_mode: renderer.width_mode,
+            time: renderer.time,
+            ..Default::default()
+        }
+    }
+
+    pub fn pages_num(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Adds page with the world rectangle `view` (min, max) fitted into the page inside margins,
+    /// `Camera::view_bounds` for what is on the screen or `vector::data_bounds` for everything.
+    /// Lines are clipped by the view
+    pub fn add_page(&mut self, page: Page, lines: &Lines, view: (DVec2, DVec2)) {
+        let (min, max) = view;
+        let size = (max - min).as_vec2();
+        let area = vec2(page.width, page.height) - Vec2::splat(2. * page.margin);
+        // points in one world unit, aspect ratio is kept and the view is centered
+        let k = (area.x() / size.x()).min(area.y() / size.y());
+        let origin = vec2(page.margin, page.margin) + (area - size * k) / 2.;
+        let scale = match self.width_mode {
+            WidthMode::World => 1.,
+            WidthMode::Pixels => 1. / k,
+        };
+        let (join, miter_limit) = match self.join {
+            LineJoin::Miter(limit) => (0, limit.max(1.)),
+            LineJoin::Round => (1, 10.),
+            LineJoin::Bevel => (2, 10.),
+        };
+
+        let mut content = String::new();
+        let clip = size * k;
+        writeln!(
+            content,
+            "{} {} {} {} re W n",
+            num(origin.x()),
+            num(origin.y()),
+            num(clip.x()),
+            num(clip.y())
+        )
+        .unwrap();
+        writeln!(content, "{} j {} M", join, num(miter_limit)).unwrap();
+        for stroke in strokes(lines.iter_with_centers(), min, scale, self.time) {
+            // view to page
+            let stroke = Stroke {
+                points: stroke.points.iter().map(|p| origin + *p * k).collect(),
+                thickness: stroke.thickness * k,
+                outline_width: stroke.outline_width * k,
+                dash: stroke.dash * k,
+                dash_offset: stroke.dash_offset * k,
+                ..stroke
+            };
+            self.write_stroke(&mut content, &stroke);
+        }
+        self.pages.push((page, content));
+    }
+
+    /// index of ExtGState with the alpha, alpha is quantized like in 8 bit color
+    fn opacity(&mut self, alpha: f32) -> usize {
+        let alpha = (alpha.clamp(0., 1.) * 255.).round() / 255.;
+        match self.opacities.iter().position(|a| *a == alpha) {
+            Some(index) => index,
+            None => {
+                self.opacities.push(alpha);
+                self.opacities.len() - 1
+            }
+        }
+    }
+
+    fn write_stroke(&mut self, content: &mut String, stroke: &Stroke) {
+        if stroke.outline_width > 0. {
+            let casing = stroke.thickness + stroke.outline_width;
+            self.write_shape(content, stroke, casing, stroke.outline_color);
+            let join = stroke.end_join(casing, self.join);
+            self.write_join(content, join, casing, stroke.outline_color);
+        }
+        let join = stroke.end_join(stroke.thickness, self.join);
+        self.write_join(content, join, stroke.thickness, stroke.end_color);
+        if stroke.color == stroke.end_color {
+            self.write_shape(content, stroke, stroke.thickness, stroke.color);
+            return;
+        }
+        // PDF shadings can't change opacity, so gradient is drawn by pieces of solid color
+        let (a, b) = (stroke.points[0], stroke.points[1]);
+        let difference = (stroke.end_color - stroke.color).abs();
+        let max = difference
+            .x()
+            .max(difference.y())
+            .max(difference.z())
+            .max(difference.w());
+        let n = ((max * 32.).ceil() as usize).clamp(1, 32);
+        for i in 0..n {
+            let (t0, t1) = (i as f32 / n as f32, (i + 1) as f32 / n as f32);
+            let piece = Stroke {
+                points: vec![a + (b - a) * t0, a + (b - a) * t1],
+                start_cap: if i == 0 {
+                    stroke.start_cap
+                } else {
+                    LineCap::Butt
+                },
+                end_cap: if i == n - 1 {
+                    stroke.end_cap
+                } else {
+                    LineCap::Butt
+                },
+                dash_offset: stroke.dash_offset + (b - a).length() * t0,
+                ..stroke.clone()
+            };
+            let t = (t0 + t1) / 2.;
+            let color = stroke.color * (1. - t) + stroke.end_color * t;
+            self.write_shape(content, &piece, stroke.thickness, color);
+        }
+    }
+
+    /// Join piece of half width `th`
+    fn write_join(&mut self, content: &mut String, shape: Option<JoinShape>, th: f32, color: Vec4) {
+        let shape = match shape {
+            Some(shape) => shape,
+            None => return,
+        };
+        let (r, g, b) = (color.x(), color.y(), color.z());
+        write!(
+            content,
+            "q /GS{} gs {} {} {} rg",
+            self.opacity(color.w()),
+            num(r),
+            num(g),
+            num(b)
+        )
+        .unwrap();
+        let points = &shape.points;
+        let last = points.len() - 1;
+        for (i, p) in points.iter().enumerate() {
+            if i == last && shape.round {
+                // arc around the vertex by one cubic Bezier curve, the angle is below 180 degrees
+                let (e, a) = (points[0], points[last - 1]);
+                let (n1, n2) = ((a - e) / th, (*p - e) / th);
+                let cos = n1.dot(n2).clamp(-1., 1.);
+                let k = 4. / 3. * (cos.acos() / 4.).tan() * th;
+                let c1 = a + (n2 - n1 * cos).normalize() * k;
+                let c2 = *p + (n1 - n2 * cos).normalize() * k;
+                for q in [c1, c2, *p].iter() {
+                    write!(content, " {} {}", num(q.x()), num(q.y())).unwrap();
+                }
+                content.push_str(" c");
+            } else {
+                let operator = if i == 0 { "m" } else { "l" };
+                write!(content, " {} {} {}", num(p.x()), num(p.y()), operator).unwrap();
+            }
+        }
+        content.push_str(" h f Q\n");
+    }
+
+    /// Stroke of half width `th`
+    fn write_shape(&mut self, content: &mut String, stroke: &Stroke, th: f32, color: Vec4) {
+        let shape = stroke.shape(th);
+        let (r, g, b) = (color.x(), color.y(), color.z());
+        writeln!(
+            content,
+            "q /GS{} gs {} {} {} RG {} {} {} rg {} w {} J",
+            self.opacity(color.w()),
+            num(r),
+            num(g),
+            num(b),
+            num(r),
+            num(g),
+            num(b),
+            num(2. * th),
+            match shape.cap {
+                LineCap::Butt => 0,
+                LineCap::Round => 1,
+                LineCap::Square => 2,
+            }
+        )
+        .unwrap();
+        let pattern = stroke.dash_pattern();
+        if !pattern.is_empty() {
+            let pattern: Vec<String> = pattern.iter().map(|d| num(*d)).collect();
+            writeln!(
+                content,
+                "[{}] {} d",
+                pattern.join(" "),
+                num(stroke.dash_offset)
+            )
+            .unwrap();
+        }
+
+        for (i, p) in shape.points.iter().enumerate() {
+            let operator = if i == 0 { "m" } else { "l" };
+            write!(content, "{} {} {} ", num(p.x()), num(p.y()), operator).unwrap();
+        }
+        content.push_str(if shape.closed { "s\n" } else { "S\n" });
+
+        // half disk of two quarter circles made of cubic Bezier curves
+        const KAPPA: f32 = 0.552_284_8;
+        for (center, t) in shape.disks {
+            let n = vec2(-t.y(), t.x()) * th;
+            let t = t * th;
+            let points = [
+                center + n,
+                center + n + t * KAPPA,
+                center + t + n * KAPPA,
+                center + t,
+                center + t - n * KAPPA,
+                center - n + t * KAPPA,
+                center - n,
+            ];
+            write!(content, "{} {} m", num(points[0].x()), num(points[0].y())).unwrap();
+            for curve in points[1..].chunks(3) {
+                for p in curve {
+                    write!(content, " {} {}", num(p.x()), num(p.y())).unwrap();
+                }
+                content.push_str(" c");
+            }
+            content.push_str(" f\n");
+        }
+        content.push_str("Q\n");
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // objects: 1 catalog, 2 page tree, 3 resources, then page and content for every page
+        let page_id = |i: usize| 4 + 2 * i;
+        let mut objects = vec![];
+        objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", page_id(i)))
+            .collect();
+        objects.push(format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            self.pages.len()
+        ));
+        let states: Vec<String> = self
+            .opacities
+            .iter()
+            .enumerate()
+            .map(|(i, a)| format!("/GS{} << /CA {} /ca {} >>", i, num(*a), num(*a)))
+            .collect();
+        objects.push(format!("<< /ExtGState << {} >> >>", states.join(" ")));
+        for (i, (page, content)) in self.pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources 3 0 R /Contents {} 0 R >>",
+                num(page.width),
+                num(page.height),
+                page_id(i) + 1
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                content.len(),
+                content
+            ));
+        }
+
+        let mut pdf = "%PDF-1.4\n".to_string();
+        let mut offsets = vec![];
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            writeln!(pdf, "{} 0 obj\n{}\nendobj", i + 1, object).unwrap();
+        }
+        let xref = pdf.len();
+        writeln!(pdf, "xref\n0 {}", objects.len() + 1).unwrap();
+        pdf.push_str("0000000000 65535 f \n");
+        for offset in offsets {
+            writeln!(pdf, "{:010} 00000 n ", offset).unwrap();
+        }
+        writeln!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+            objects.len() + 1,
+            xref
+        )
+        .unwrap();
+        pdf.into_bytes()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+}
+
+/// PDF numbers can't have exponent, 0.001 pt is far below what printers can show
+fn num(value: f32) -> String {
+    let s = format!("{:.3}", value);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => "0".to_string(),
+        s => s.to_string(),
+    }
+}
@@ -0,0 +1,94 @@
+// Hit testing of lines. Distances are computed by the CPU copy of hex_shader (raster),
+// so thickness, caps, joins, dashes and SegmentType discards are the same as on the screen
+// and hover or click finds exactly what is drawn under the cursor.
+
+use glam::Vec2;
+
+use crate::camera::Camera;
+use crate::draw_lines::{Line, LineHandle, Lines, WidthMode};
+use crate::dvec2::DVec2;
+use crate::raster::{line_distance, Raster};
+use crate::spatial::join_extent;
+
+/// Line found by `LinesRenderer::pick`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Picked {
+    /// line of `LinesRenderer::lines`
+    Line(LineHandle),
+    /// line of the renderer tiles, index in level 0 of `TileSet::lod` (see `LodPyramid::line`)
+    Tile(usize),
+}
+
+/// Lines within `tolerance_px` pixels of `world_point` as they are drawn by the camera on the screen
+/// of width x height pixels, the closest first. Lines under the point go first, the top one
+/// (drawn last) first of all. `raster` has the renderer settings, see `Raster::from_renderer`
+pub fn pick<P: Into<DVec2>>(
+    lines: &Lines,
+    raster: &Raster,
+    camera: &Camera,
+    width: f32,
+    height: f32,
+    world_point: P,
+    tolerance_px: f32,
+) -> Vec<LineHandle> {
+    let hits = pick_from(
+        |i| line_at(lines, i),
+        0..lines.len(),
+        raster,
+        camera,
+        width,
+        height,
+        world_point.into(),
+        tolerance_px,
+    );
+    hits.into_iter().map(|(_, i)| lines.handle_at(i)).collect()
+}
+
+pub(crate) fn line_at(lines: &Lines, index: usize) -> (Line, DVec2) {
+    let (line, center) = lines.line_with_center(index);
+    (*line, center)
+}
+
+/// `pick` among lines with indices `candidates`, `line` gives the line with its f64 position
+/// by index. Returns (distance in world units, index) in `pick` order
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pick_from<I: IntoIterator<Item = usize>, F: Fn(usize) -> (Line, DVec2)>(
+    line: F,
+    candidates: I,
+    raster: &Raster,
+    camera: &Camera,
+    width: f32,
+    height: f32,
+    world_point: DVec2,
+    tolerance_px: f32,
+) -> Vec<(f32, usize)> {
+    let uniforms = raster.uniforms(camera, width, height);
+    let pixel = uniforms.pixel();
+    let tolerance = tolerance_px * pixel;
+    let width_scale = match raster.width_mode {
+        WidthMode::World => 1.,
+        WidthMode::Pixels => pixel,
+    };
+    // nothing is further from the segment than joins with the miter limit
+    let miter = join_extent(raster.join);
+    // (distance, index)
+    let mut hits: Vec<(f32, usize)> = vec![];
+    for i in candidates {
+        let (line, center) = line(i);
+        // the point relative to the line, so there is no precision loss far from the origin
+        let p = (world_point - center).as_vec2();
+        let extent = (line.thickness + line.outline_width) * width_scale * miter;
+        if p.length() > line.dir.length() / 2. + extent + tolerance {
+            continue;
+        }
+        let mut relative = line;
+        relative.position = Vec2::zero();
+        if let Some(distance) = line_distance(&relative, p, &uniforms) {
+            if distance <= tolerance {
+                hits.push((distance.max(0.), i));
+            }
+        }
+    }
+    hits.sort_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)));
+    hits
+}
@@ -0,0 +1,442 @@
+// CPU reference implementation of hex_shader from draw_lines.
+// It's slow, but works without GPU, so pictures can be made and compared anywhere (CI, tests).
+// Keep it in sync with the shader: every step here has its counterpart there.
+
+use std::{fs::File, io, io::BufWriter, path::Path};
+
+use glam::{vec2, vec4, Mat4, Vec2, Vec4};
+
+use crate::camera::Camera;
+use crate::draw_lines::{Line, LineJoin, Lines, LinesRenderer, WidthMode};
+use crate::dvec2::DVec2;
+
+/// RGBA8 picture, rows go from top to bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, clear_color: Vec4) -> Self {
+        let pixel = [
+            to_u8(clear_color.x()),
+            to_u8(clear_color.y()),
+            to_u8(clear_color.z()),
+            to_u8(clear_color.w()),
+        ];
+        Image {
+            width,
+            height,
+            data: pixel.repeat((width * height) as usize),
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+
+    /// PNG file content
+    pub fn encode_png(&self) -> Vec<u8> {
+        let mut png = vec![];
+        self.write_png(&mut png)
+            .expect("writing PNG to memory doesn't fail");
+        png
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    fn write_png<W: io::Write>(&self, w: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)?;
+        Ok(())
+    }
+
+    /// Pixels are RGBA with straight alpha like in PNG, GPU keeps premultiplied ones
+    /// (see `LinesRenderer` pipeline), so color is divided by alpha where it isn't zero
+    pub(crate) fn unpremultiply(&mut self) {
+        for pixel in self.data.chunks_mut(4) {
+            let a = pixel[3] as u32;
+            for channel in pixel[..3].iter_mut() {
+                if let Some(value) = (*channel as u32 * 255 + a / 2).checked_div(a) {
+                    *channel = value.min(255) as u8;
+                }
+            }
+        }
+    }
+
+    /// The same blending as LinesRenderer pipeline: premultiplied color with One, OneMinusSourceAlpha.
+    /// The image keeps straight alpha, so the result is divided by its alpha again
+    fn blend(&mut self, x: u32, y: u32, color: Vec4) {
+        let i = ((y * self.width + x) * 4) as usize;
+        let a = color.w().clamp(0., 1.);
+        if a == 0. {
+            return;
+        }
+        let dst_a = self.data[i + 3] as f32 / 255.;
+        let out_a = a + dst_a * (1. - a);
+        for (channel, value) in [color.x(), color.y(), color.z()].iter().enumerate() {
+            let dst = self.data[i + channel] as f32 / 255.;
+            let premultiplied = value.clamp(0., 1.) * a + dst * dst_a * (1. - a);
+            self.data[i + channel] = to_u8(premultiplied / out_a);
+        }
+        self.data[i + 3] = to_u8(out_a);
+    }
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value.clamp(0., 1.) * 255.).round() as u8
+}
+
+/// Uniforms of hex_shader
+pub(crate) struct Uniforms {
+    mvp11: f32,
+    join: f32,
+    miter_limit: f32,
+    width_mode: f32,
+    viewport: Vec2,
+    time: f32,
+    aa_width: f32,
+}
+
+impl Uniforms {
+    /// world units in one pixel
+    pub(crate) fn pixel(&self) -> f32 {
+        2. / (self.mvp11 * self.viewport.y())
+    }
+}
+
+/// LinesRenderer settings which change the picture
+#[derive(Debug, Clone, Copy)]
+pub struct Raster {
+    pub join: LineJoin,
+    pub width_mode: WidthMode,
+    pub time: f32,
+    /// in pixels, like LinesRenderer::aa_width
+    pub aa_width: f32,
+}
+
+impl Default for Raster {
+    fn default() -> Self {
+        Raster {
+            join: LineJoin::default(),
+            width_mode: WidthMode::default(),
+            time: 0.,
+            aa_width: 1.,
+        }
+    }
+}
+
+impl Raster {
+    pub fn from_renderer(renderer: &LinesRenderer) -> Self {
+        Raster {
+            join: renderer.join,
+            width_mode: renderer.width_mode,
+            time: renderer.time,
+            aa_width: renderer.aa_width,
+        }
+    }
+
+    pub(crate) fn uniforms(&self, camera: &Camera, width: f32, height: f32) -> Uniforms {
+        let projection = camera.get_projection(height / width);
+        let (join, miter_limit) = self.join.as_uniform();
+        Uniforms {
+            mvp11: projection.to_cols_array_2d()[1][1],
+            join,
+            miter_limit,
+            width_mode: match self.width_mode {
+                WidthMode::World => 0.,
+                WidthMode::Pixels => 1.,
+            },
+            viewport: vec2(width, height),
+            time: self.time,
+            aa_width: self.aa_width,
+        }
+    }
+
+    /// Draws lines in order over the image, like LinesRenderer::draw does with the screen
+    pub fn draw(&self, image: &mut Image, lines: &Lines, camera: &Camera) {
+        self.draw_lines(image, lines.iter_with_centers(), camera);
+    }
+
+    /// `draw` for (line, f64 position) pairs. Lines are rebased on the camera eye in f64,
+    /// like on GPU, so they are drawn the same far from the origin
+    pub(crate) fn draw_lines<'a, I: IntoIterator<Item = (&'a Line, DVec2)>>(
+        &self,
+        image: &mut Image,
+        lines: I,
+        camera: &Camera,
+    ) {
+        let (width, height) = (image.width as f32, image.height as f32);
+        let mvp = camera.get_projection(height / width);
+        let uniforms = self.uniforms(camera, width, height);
+        // projection is affine, so world position of pixel is origin + x * dx + y * dy
+        let inverse = mvp.inverse();
+        let unproject = |x: f32, y: f32| {
+            let p = inverse * vec4(-1. + 2. * x / width, 1. - 2. * y / height, 0., 1.);
+            vec2(p.x(), p.y())
+        };
+        let origin = unproject(0., 0.);
+        let dx = unproject(1., 0.) - origin;
+        let dy = unproject(0., 1.) - origin;
+
+        for (line, center) in lines {
+            let mut line = *line;
+            line.position = (center - camera.position2d).as_vec2();
+            let line = &line;
+            let vertex = vertex(line, &uniforms);
+            let (min, max) = screen_bounds(&vertex, &mvp, width, height);
+            // pixel centers inside the quad bounds
+            let x0 = (min.x() - 0.5).ceil().max(0.) as u32;
+            let y0 = (min.y() - 0.5).ceil().max(0.) as u32;
+            let x1 = (max.x() - 0.5).floor().min(width - 1.);
+            let y1 = (max.y() - 0.5).floor().min(height - 1.);
+            if x1 < 0. || y1 < 0. {
+                continue;
+            }
+            for y in y0..=y1 as u32 {
+                for x in x0..=x1 as u32 {
+                    let p = origin + dx * (x as f32 + 0.5) + dy * (y as f32 + 0.5);
+                    if let Some(color) = fragment(line, &vertex, p, &uniforms) {
+                        image.blend(x, y, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Values computed by the vertex shader, they are the same for every vertex of the instance
+struct Vertex {
+    th: f32,
+    ow: f32,
+    ds: Vec4,
+    pth: Vec2,
+    /// world quad corners
+    corners: [Vec2; 4],
+}
+
+fn vertex(line: &Line, u: &Uniforms) -> Vertex {
+    let scale = if u.width_mode > 0.5 {
+        2. / (u.mvp11 * u.viewport.y())
+    } else {
+        1.
+    };
+    let th = line.thickness * scale;
+    let ow = line.outline_width * scale;
+    let t = line.dir / line.dir.length();
+    let n = vec2(-t.y(), t.x());
+    let mut ext = th + ow;
+    if u.join < 0.5 {
+        ext *= u.miter_limit.max(1.);
+    }
+    let along = line.dir / 2. + t * ext;
+    let across = n * (th + ow) * 1.1;
+    Vertex {
+        th,
+        ow,
+        ds: line.dash * scale,
+        pth: vec2(
+            line.distance,
+            (line.dash_offset - line.flow_speed * u.time) * scale,
+        ),
+        corners: [
+            line.position - along - across,
+            line.position - along + across,
+            line.position + along - across,
+            line.position + along + across,
+        ],
+    }
+}
+
+fn screen_bounds(vertex: &Vertex, mvp: &Mat4, width: f32, height: f32) -> (Vec2, Vec2) {
+    let mut min = vec2(f32::MAX, f32::MAX);
+    let mut max = vec2(f32::MIN, f32::MIN);
+    for corner in vertex.corners.iter() {
+        let p = *mvp * vec4(corner.x(), corner.y(), 0., 1.);
+        let screen = vec2((p.x() + 1.) * width / 2., (1. - p.y()) * height / 2.);
+        min = min.min(screen);
+        max = max.max(screen);
+    }
+    (min, max)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+/// coverage of hex_shader
+fn coverage(d: f32, border: f32) -> f32 {
+    if border <= 0. {
+        return if d < 0. { 1. } else { 0. };
+    }
+    1. - smoothstep(-border, 0., d)
+}
+
+fn mix(a: Vec4, b: Vec4, t: f32) -> Vec4 {
+    a * (1. - t) + b * t
+}
+
+/// GLSL mod
+fn modulo(x: f32, y: f32) -> f32 {
+    x - y * (x / y).floor()
+}
+
+/// Distance from `p` to the segment [a, b], line_segment of hex_shader
+pub fn line_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ba = b - a;
+    let pa = p - a;
+    let h = (pa.dot(ba) / ba.dot(ba)).clamp(0., 1.);
+    (pa - ba * h).length()
+}
+
+fn join_distance(p: Vec2, e: Vec2, t_out: Vec2, w: Vec2, th: f32, u: &Uniforms) -> f32 {
+    let n = vec2(-t_out.y(), t_out.x());
+    let across = (p - e).dot(n).abs() - th;
+    if u.join > 0.5 && u.join < 1.5 {
+        return (p - e).length() - th;
+    }
+    let m = t_out - w;
+    if m.dot(m) < 0.0001 {
+        return across;
+    }
+    let m = m.normalize();
+    let sin_half = n.dot(m).abs();
+    if u.join < 0.5 && sin_half * u.miter_limit >= 1. {
+        return across;
+    }
+    across.max((p - e).dot(m) - th * sin_half)
+}
+
+fn cap_distance(p: Vec2, e: Vec2, t_out: Vec2, cap: f32, th: f32) -> f32 {
+    let n = vec2(-t_out.y(), t_out.x());
+    let across = (p - e).dot(n).abs() - th;
+    let along = (p - e).dot(t_out);
+    if cap < 0.5 {
+        return across.max(along);
+    }
+    across.max(along - th)
+}
+
+fn dash_distance(q: f32, l: f32, r: f32, c: f32, cap: f32, th: f32) -> f32 {
+    let along = (l - q).max(q - r);
+    if cap < 0.5 {
+        (c - th).max(along)
+    } else if cap < 1.5 {
+        (c - th).max(along - th)
+    } else {
+        vec2(along.max(0.), c).length() - th
+    }
+}
+
+fn periodic_dash(q: f32, l: f32, r: f32, c: f32, total: f32, cap: f32, th: f32) -> f32 {
+    [-total, 0., total]
+        .iter()
+        .map(|shift| dash_distance(q, l + shift, r + shift, c, cap, th))
+        .fold(f32::INFINITY, f32::min)
+}
+
+fn dashes(q: f32, c: f32, ds: Vec4, cap: f32, th: f32) -> f32 {
+    let total = ds.x() + ds.y() + ds.z() + ds.w();
+    let q = modulo(q, total);
+    let mut d = periodic_dash(q, 0., ds.x(), c, total, cap, th);
+    if ds.z() > 0. {
+        let l = ds.x() + ds.y();
+        d = d.min(periodic_dash(q, l, l + ds.z(), c, total, cap, th));
+    }
+    d
+}
+
+/// Distance in world units from `world` to the line with its outline as the shader draws it
+/// (negative inside), None where fragments of the line are discarded.
+/// Lines are found by it where they are drawn
+pub(crate) fn line_distance(line: &Line, world: Vec2, u: &Uniforms) -> Option<f32> {
+    let vertex = vertex(line, u);
+    distance(line, &vertex, world, u).map(|(d, _)| d - vertex.ow)
+}
+
+/// (distance to the line without outline, position along the segment)
+fn distance(line: &Line, vertex: &Vertex, world: Vec2, u: &Uniforms) -> Option<(f32, f32)> {
+    let th = vertex.th;
+    let dr = line.dir;
+    let p = world - (line.position - dr / 2.);
+    let a = Vec2::zero();
+    let b = dr;
+    let t = dr.normalize();
+    let h = p.dot(dr) / dr.dot(dr);
+    let mut d = line_segment(p, a, b) - th;
+
+    let start_join = line.prev_dir.dot(line.prev_dir) > 0.;
+    let end_join = line.next_dir.dot(line.next_dir) > 0.;
+    if start_join {
+        let w = -line.prev_dir.normalize();
+        if (p - a).dot(t - w) < 0. {
+            return None;
+        }
+        if h < 0. {
+            d = join_distance(p, a, -t, w, th, u);
+        }
+    } else if h < 0. && line.caps.x() < 1.5 {
+        d = cap_distance(p, a, -t, line.caps.x(), th);
+    }
+    if end_join {
+        let w = line.next_dir.normalize();
+        if (p - b).dot(-t - w) < 0. {
+            return None;
+        }
+        if h > 1. {
+            d = join_distance(p, b, t, w, th, u);
+        }
+    } else if h > 1. && line.caps.y() < 1.5 {
+        d = cap_distance(p, b, t, line.caps.y(), th);
+    }
+
+    let ds = vertex.ds;
+    if ds.x() + ds.y() + ds.z() + ds.w() > 0. {
+        let n = vec2(-t.y(), t.x());
+        let q = vertex.pth.x() + vertex.pth.y() + (p - a).dot(t);
+        d = d.max(dashes(q, (p - a).dot(n).abs(), ds, line.caps.x(), th));
+    }
+
+    let st = line.segment_type;
+    let no_first = (st - 1.).abs() < 0.01 || (st - 3.).abs() < 0.01;
+    let no_second = (st - 2.).abs() < 0.01 || (st - 3.).abs() < 0.01;
+    if (!start_join && no_first && h < 0.) || (!end_join && no_second && h > 1.) {
+        return None;
+    }
+    Some((d, h))
+}
+
+/// Color of the fragment before blending, None if it's discarded
+fn fragment(line: &Line, vertex: &Vertex, world: Vec2, u: &Uniforms) -> Option<Vec4> {
+    let ow = vertex.ow;
+    let (d, h) = distance(line, vertex, world, u)?;
+    // aa_width in world units
+    let border = u.aa_width * 2. / (u.mvp11 * u.viewport.y());
+
+    let outer = d - ow;
+    if outer >= 0. {
+        // transparent fragment doesn't change anything with this blending
+        return None;
+    }
+    let smooth = coverage(outer, border);
+    let mut color = mix(line.color, line.end_color, h.clamp(0., 1.));
+    if ow > 0. {
+        color = mix(line.outline_color, color, coverage(d, border));
+    }
+    *color.w_mut() *= smooth;
+    Some(color)
+}
@@ -0,0 +1,151 @@
+// Pictures of what LinesRenderer shows, with any size independent of the window.
+// GPU path draws into offscreen texture and reads it back,
+// CPU path draws the same tiles and lines with raster (no context needed, the size isn't limited by GPU).
+
+use glam::Vec4;
+use miniquad::*;
+
+use crate::camera::Camera;
+use crate::draw_lines::{Line, LinesRenderer};
+use crate::dvec2::DVec2;
+use crate::raster::{Image, Raster};
+
+/// Offscreen render target, keep it around for repeated screenshots of the same size:
+/// miniquad can't delete render passes
+pub struct Screenshot {
+    texture: Texture,
+    pass: RenderPass,
+    pub clear_color: Vec4,
+}
+
+impl Screenshot {
+    /// Size is limited by GL_MAX_TEXTURE_SIZE of the GPU
+    pub fn new(ctx: &mut Context, width: u32, height: u32, clear_color: Vec4) -> Self {
+        let texture = Texture::new_render_texture(
+            ctx,
+            TextureParams {
+                format: TextureFormat::RGBA8,
+                width,
+                height,
+                ..Default::default()
+            },
+        );
+        let pass = RenderPass::new(ctx, texture, None);
+        Screenshot {
+            texture,
+            pass,
+            clear_color,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.texture.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.texture.height
+    }
+
+    /// Draws renderer lines as seen by camera and reads the picture back with straight alpha.
+    /// Call it outside of other passes (e.g. before `begin_default_pass`)
+    pub fn take(&self, ctx: &mut Context, renderer: &mut LinesRenderer, camera: &Camera) -> Image {
+        // the pipeline blends premultiplied colors
+        let c = self.clear_color;
+        let a = c.w();
+        ctx.begin_pass(
+            self.pass,
+            PassAction::clear_color(c.x() * a, c.y() * a, c.z() * a, a),
+        );
+        renderer.draw_to(ctx, camera, self.width() as f32, self.height() as f32);
+        let image = self.read_pixels(renderer, camera);
+        ctx.end_render_pass();
+        image
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read_pixels(&self, _renderer: &mut LinesRenderer, _camera: &Camera) -> Image {
+        let (width, height) = (self.width(), self.height());
+        let mut image = Image::new(width, height, self.clear_color);
+        unsafe {
+            gl::glReadPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                gl::GL_RGBA,
+                gl::GL_UNSIGNED_BYTE,
+                image.data.as_mut_ptr() as *mut _,
+            );
+        }
+        // GL rows go from bottom to top
+        let row = width as usize * 4;
+        image.data = image.data.chunks(row).rev().flatten().copied().collect();
+        image.unpremultiply();
+        image
+    }
+
+    /// There is no glReadPixels in miniquad's wasm bindings, the picture is drawn on CPU instead
+    #[cfg(target_arch = "wasm32")]
+    fn read_pixels(&self, renderer: &mut LinesRenderer, camera: &Camera) -> Image {
+        take_cpu(
+            renderer,
+            camera,
+            self.width(),
+            self.height(),
+            self.clear_color,
+        )
+    }
+}
+
+/// Screenshot without GPU, drawn with raster module. Everything `LinesRenderer::draw` shows
+/// is drawn: visible tiles, then lines of the LOD level for the picture size.
+/// Slow for millions of lines, but the size isn't limited and it doesn't touch GPU state
+pub fn take_cpu(
+    renderer: &mut LinesRenderer,
+    camera: &Camera,
+    width: u32,
+    height: u32,
+    clear_color: Vec4,
+) -> Image {
+    let (w, h) = (width as f32, height as f32);
+    let mut image = Image::new(width, height, clear_color);
+    let raster = Raster::from_renderer(renderer);
+    let (width_mode, join) = (renderer.width_mode, renderer.join);
+    let tolerance = renderer.lod_tolerance;
+    if let Some(tiles) = renderer.tiles_mut() {
+        tiles.set_width_mode(width_mode);
+        tiles.set_join(join);
+        for key in tiles.visible_tiles(camera, w, h, tolerance) {
+            let lines: Vec<(Line, DVec2)> = tiles
+                .tile_lines(key)
+                .into_iter()
+                .map(|i| tiles.lod().line(key.level, i))
+                .collect();
+            let tile_lines = lines.iter().map(|(line, center)| (line, *center));
+            raster.draw_lines(&mut image, tile_lines, camera);
+        }
+    }
+    renderer.update_lod(camera, w);
+    raster.draw(&mut image, &renderer.lines, camera);
+    image
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod gl {
+    pub const GL_RGBA: u32 = 0x1908;
+    pub const GL_UNSIGNED_BYTE: u32 = 0x1401;
+
+    // miniquad doesn't expose reading pixels back, GL library is linked by it anyway
+    #[cfg_attr(windows, link(name = "opengl32"))]
+    extern "C" {
+        pub fn glReadPixels(
+            x: i32,
+            y: i32,
+            width: i32,
+            height: i32,
+            format: u32,
+            type_: u32,
+            pixels: *mut std::ffi::c_void,
+        );
+    }
+}
@@ -0,0 +1,199 @@
+// Polyline simplification to apply before building Lines: big extracts keep their shape
+// with a fraction of the segments. Computations are in f64, so Web Mercator meters are fine.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::camera::Camera;
+use crate::dvec2::DVec2;
+
+/// How far simplified polyline may go from the original one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    World(f32),
+    /// Pixels on the screen of `screen_width` pixels at camera `zoom`
+    Pixels {
+        pixels: f32,
+        zoom: f32,
+        screen_width: f32,
+    },
+}
+
+impl Tolerance {
+    pub fn pixels(pixels: f32, camera: &Camera, screen_width: f32) -> Self {
+        Tolerance::Pixels {
+            pixels,
+            zoom: camera.zoom,
+            screen_width,
+        }
+    }
+
+    pub fn world(self) -> f64 {
+        match self {
+            Tolerance::World(tolerance) => tolerance as f64,
+            // camera shows 1 / zoom world units across the screen width
+            Tolerance::Pixels {
+                pixels,
+                zoom,
+                screen_width,
+            } => pixels as f64 / (zoom as f64 * screen_width as f64),
+        }
+    }
+}
+
+impl From<f32> for Tolerance {
+    fn from(tolerance: f32) -> Self {
+        Tolerance::World(tolerance)
+    }
+}
+
+/// distance from p to segment ab, like line_segment in hex_shader
+fn segment_distance(p: DVec2, a: DVec2, b: DVec2) -> f64 {
+    let (pa, ba) = (p - a, b - a);
+    let len2 = ba.x * ba.x + ba.y * ba.y;
+    if len2 == 0. {
+        return pa.length();
+    }
+    let h = ((pa.x * ba.x + pa.y * ba.y) / len2).clamp(0., 1.);
+    (pa - ba * h).length()
+}
+
+fn triangle_area(a: DVec2, b: DVec2, c: DVec2) -> f64 {
+    let (ab, ac) = (b - a, c - a);
+    (ab.x * ac.y - ab.y * ac.x).abs() / 2.
+}
+
+/// Ramer-Douglas-Peucker: keeps points which are further than tolerance from the simplified
+/// polyline, so corners are kept and straight runs lose their points.
+/// The first and the last points are always kept
+pub fn douglas_peucker<P, T>(points: &[P], tolerance: T) -> Vec<P>
+where
+    P: Copy + Into<DVec2>,
+    T: Into<Tolerance>,
+{
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let tolerance = tolerance.into().world();
+    let positions: Vec<DVec2> = points.iter().map(|p| (*p).into()).collect();
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // no recursion, long ways would overflow the stack
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let (a, b) = (positions[first], positions[last]);
+        let mut farthest = (0., first);
+        for (i, p) in positions.iter().enumerate().take(last).skip(first + 1) {
+            let distance = segment_distance(*p, a, b);
+            if distance > farthest.0 {
+                farthest = (distance, i);
+            }
+        }
+        let (distance, i) = farthest;
+        if distance > tolerance {
+            keep[i] = true;
+            ranges.push((first, i));
+            ranges.push((i, last));
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(p, _)| *p)
+        .collect()
+}
+
+/// point in Visvalingam heap, the smallest area comes first
+struct Candidate {
+    area: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .area
+            .partial_cmp(&self.area)
+            .unwrap_or(Ordering::Equal)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// Visvalingam-Whyatt: removes points with the smallest triangle formed with their neighbours
+/// while the area is below tolerance². Gives smoother shapes than Douglas-Peucker.
+/// The first and the last points are always kept
+pub fn visvalingam<P, T>(points: &[P], tolerance: T) -> Vec<P>
+where
+    P: Copy + Into<DVec2>,
+    T: Into<Tolerance>,
+{
+    let n = points.len();
+    if n < 3 {
+        return points.to_vec();
+    }
+    let tolerance = tolerance.into().world();
+    let min_area = tolerance * tolerance;
+    let positions: Vec<DVec2> = points.iter().map(|p| (*p).into()).collect();
+    // doubly linked list of points which are still in the polyline
+    let mut prev: Vec<usize> = (0..n).map(|i| i.wrapping_sub(1)).collect();
+    let mut next: Vec<usize> = (1..=n).collect();
+    let mut areas = vec![f64::INFINITY; n];
+    let mut heap = BinaryHeap::new();
+    for i in 1..n - 1 {
+        areas[i] = triangle_area(positions[i - 1], positions[i], positions[i + 1]);
+        heap.push(Candidate {
+            area: areas[i],
+            index: i,
+        });
+    }
+    let mut removed = vec![false; n];
+    let mut max_removed_area: f64 = 0.;
+    while let Some(Candidate { area, index }) = heap.pop() {
+        if removed[index] || area != areas[index] {
+            // outdated after neighbour removal
+            continue;
+        }
+        if area >= min_area {
+            break;
+        }
+        // effective area never decreases, otherwise removal order depends on the previous ones
+        max_removed_area = max_removed_area.max(area);
+        removed[index] = true;
+        let (p, q) = (prev[index], next[index]);
+        next[p] = q;
+        prev[q] = p;
+        for i in [p, q].iter().copied() {
+            if i == 0 || i == n - 1 {
+                continue;
+            }
+            let area = triangle_area(positions[prev[i]], positions[i], positions[next[i]]);
+            areas[i] = area.max(max_removed_area);
+            heap.push(Candidate {
+                area: areas[i],
+                index: i,
+            });
+        }
+    }
+    points
+        .iter()
+        .zip(removed)
+        .filter(|(_, removed)| !*removed)
+        .map(|(p, _)| *p)
+        .collect()
+}
@@ -0,0 +1,256 @@
+// Spatial index over segments for viewport culling: static R-tree bulk loaded with
+// sort-tile-recursive packing. Bounds are in f64, so it works for big world coordinates.
+
+use std::ops::Range;
+
+use crate::camera::Camera;
+use crate::draw_lines::{Line, LineJoin, Lines, WidthMode};
+use crate::dvec2::{dvec2, DVec2};
+
+/// Children in R-tree node
+const NODE_SIZE: usize = 16;
+
+/// How many half widths caps and joins stick out of the segment: square caps by √2,
+/// round joins by 1, miter joins by the miter limit
+pub(crate) fn join_extent(join: LineJoin) -> f32 {
+    match join {
+        LineJoin::Miter(limit) => limit.max(2.),
+        LineJoin::Round | LineJoin::Bevel => 2.,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min: DVec2,
+    max: DVec2,
+}
+
+impl Bounds {
+    fn empty() -> Self {
+        Bounds {
+            min: dvec2(f64::INFINITY, f64::INFINITY),
+            max: dvec2(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    fn center(&self) -> DVec2 {
+        (self.min + self.max) / 2.
+    }
+
+    fn union(self, other: Bounds) -> Bounds {
+        Bounds {
+            min: dvec2(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: dvec2(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+
+    fn intersects(&self, other: &Bounds) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    bounds: Bounds,
+    /// range in `entries` for leaves, in `nodes` for others
+    children: Range<usize>,
+    leaf: bool,
+}
+
+/// Index of lines by their bounds, built once for `Lines` and rebuilt after their change.
+/// Queries return indices of lines in `Lines::iter` order
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    /// (bounds, line index), ordered by leaves
+    entries: Vec<(Bounds, usize)>,
+    nodes: Vec<Node>,
+    root: Option<usize>,
+    /// with WidthMode::Pixels the biggest extent of lines in pixels, it depends on zoom
+    pixel_extent: f32,
+    width_mode: WidthMode,
+    join_extent: f32,
+}
+
+impl SpatialIndex {
+    /// Bounds depend on the width mode and on the join of the renderer, see `LinesRenderer::join`
+    pub fn new(lines: &Lines, width_mode: WidthMode, join: LineJoin) -> Self {
+        let lines = lines
+            .iter_with_centers()
+            .map(|(line, center)| (*line, center));
+        Self::from_lines(lines, width_mode, join)
+    }
+
+    /// Index of lines with their f64 positions, e.g. `LodPyramid::lines`
+    pub(crate) fn from_lines(
+        lines: impl Iterator<Item = (Line, DVec2)>,
+        width_mode: WidthMode,
+        join: LineJoin,
+    ) -> Self {
+        let join_extent = join_extent(join);
+        let mut pixel_extent: f32 = 0.;
+        let mut entries: Vec<(Bounds, usize)> = lines
+            .enumerate()
+            .map(|(i, (line, center))| {
+                if width_mode == WidthMode::Pixels {
+                    pixel_extent = pixel_extent.max(pixel_extent_of(&line, join_extent));
+                }
+                let half = half_size(&line, width_mode, join_extent);
+                let bounds = Bounds {
+                    min: center - half,
+                    max: center + half,
+                };
+                (bounds, i)
+            })
+            .collect();
+
+        let mut nodes = vec![];
+        let mut level: Vec<usize> = vec![];
+        sort_tile_recursive(&mut entries, |(bounds, _)| bounds.center());
+        for start in (0..entries.len()).step_by(NODE_SIZE) {
+            let children = start..entries.len().min(start + NODE_SIZE);
+            let bounds = entries[children.clone()]
+                .iter()
+                .fold(Bounds::empty(), |sum, (bounds, _)| sum.union(*bounds));
+            level.push(nodes.len());
+            nodes.push(Node {
+                bounds,
+                children,
+                leaf: true,
+            });
+        }
+        // upper levels are packed the same way until there is one root
+        while level.len() > 1 {
+            let mut level_nodes: Vec<Node> = level.iter().map(|i| nodes[*i].clone()).collect();
+            sort_tile_recursive(&mut level_nodes, |node| node.bounds.center());
+            let first = nodes.len();
+            nodes.extend(level_nodes);
+            let last = nodes.len();
+            level.clear();
+            for start in (first..last).step_by(NODE_SIZE) {
+                let children = start..last.min(start + NODE_SIZE);
+                let bounds = nodes[children.clone()]
+                    .iter()
+                    .fold(Bounds::empty(), |sum, node| sum.union(node.bounds));
+                level.push(nodes.len());
+                nodes.push(Node {
+                    bounds,
+                    children,
+                    leaf: false,
+                });
+            }
+        }
+        SpatialIndex {
+            entries,
+            nodes,
+            root: level.first().copied(),
+            pixel_extent,
+            width_mode,
+            join_extent,
+        }
+    }
+
+    /// The index was built with these settings
+    pub(crate) fn is_for(&self, width_mode: WidthMode, join: LineJoin) -> bool {
+        self.width_mode == width_mode && self.join_extent == join_extent(join)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lines which bounds intersect world rectangle (min, max), in ascending order,
+    /// so they are drawn in the same order as all lines
+    pub fn query(&self, min: DVec2, max: DVec2) -> Vec<usize> {
+        let area = Bounds { min, max };
+        let mut found = vec![];
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !node.bounds.intersects(&area) {
+                continue;
+            }
+            if node.leaf {
+                found.extend(
+                    self.entries[node.children.clone()]
+                        .iter()
+                        .filter(|(bounds, _)| bounds.intersects(&area))
+                        .map(|(_, i)| *i),
+                );
+            } else {
+                stack.extend(node.children.clone());
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+
+    /// Lines intersecting the view of camera on the screen of width x height pixels
+    pub fn visible(&self, camera: &Camera, width: f32, height: f32) -> Vec<usize> {
+        let (min, max) = self.view_bounds(camera, width, height);
+        self.query(min, max)
+    }
+
+    /// Candidates for picking: lines within `tolerance_px` pixels of the point, with the camera
+    /// on the screen of `width` pixels
+    pub(crate) fn near(
+        &self,
+        point: DVec2,
+        camera: &Camera,
+        width: f32,
+        tolerance_px: f32,
+    ) -> Vec<usize> {
+        let pixel = 1. / (camera.zoom as f64 * width as f64);
+        let extent = (tolerance_px + self.pixel_extent) as f64 * pixel;
+        let extent = dvec2(extent, extent);
+        self.query(point - extent, point + extent)
+    }
+
+    /// Camera view extended by the pixel width of lines
+    pub(crate) fn view_bounds(&self, camera: &Camera, width: f32, height: f32) -> (DVec2, DVec2) {
+        let top_left = camera.unproject_f64(0., 0., width, height);
+        let bottom_right = camera.unproject_f64(width, height, width, height);
+        let pixel = (bottom_right.x - top_left.x) / width as f64;
+        let extent = self.pixel_extent as f64 * pixel;
+        (
+            dvec2(top_left.x - extent, bottom_right.y - extent),
+            dvec2(bottom_right.x + extent, top_left.y + extent),
+        )
+    }
+}
+
+/// Line extent from its center in world units, without the width for WidthMode::Pixels.
+/// `join_extent` is from `join_extent`
+pub(crate) fn half_size(line: &Line, width_mode: WidthMode, join_extent: f32) -> DVec2 {
+    let extent = match width_mode {
+        WidthMode::World => ((line.thickness + line.outline_width) * join_extent) as f64,
+        WidthMode::Pixels => 0.,
+    };
+    dvec2(
+        (line.dir.x().abs() / 2.) as f64 + extent,
+        (line.dir.y().abs() / 2.) as f64 + extent,
+    )
+}
+
+/// Extent of the line width in pixels for WidthMode::Pixels
+fn pixel_extent_of(line: &Line, join_extent: f32) -> f32 {
+    (line.thickness + line.outline_width) * join_extent
+}
+
+/// Orders items so every NODE_SIZE consecutive ones are close to each other:
+/// vertical slices by x, then runs by y inside of every slice
+fn sort_tile_recursive<T, F: Fn(&T) -> DVec2>(items: &mut [T], center: F) {
+    let leaves = items.len().div_ceil(NODE_SIZE);
+    let slices = (leaves as f64).sqrt().ceil().max(1.) as usize;
+    let slice_len = slices * NODE_SIZE;
+    items.sort_unstable_by(|a, b| center(a).x.total_cmp(&center(b).x));
+    for slice in items.chunks_mut(slice_len) {
+        slice.sort_unstable_by(|a, b| center(a).y.total_cmp(&center(b).y));
+    }
+}
@@ -0,0 +1,227 @@
+// SVG export of lines. Polylines become <path> elements joined like in LinesRenderer,
+// separate segments become <line>. World y goes up, so the picture is flipped inside of <g>.
+// Coordinates are relative to the bottom left corner of the view, lines are rebased on it in f64,
+// so big world coordinates don't lose precision.
+
+use std::fmt::Write;
+
+use glam::{vec2, Vec4};
+
+use crate::draw_lines::{LineCap, LineJoin, Lines, LinesRenderer, WidthMode};
+use crate::dvec2::DVec2;
+use crate::vector::{strokes, JoinShape, Stroke};
+
+/// LinesRenderer settings which change the picture
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Svg {
+    pub join: LineJoin,
+    pub width_mode: WidthMode,
+    pub time: f32,
+}
+
+impl Svg {
+    pub fn from_renderer(renderer: &LinesRenderer) -> Self {
+        Svg {
+            join: renderer.join,
+            width_mode: renderer.width_mode,
+            time: renderer.time,
+        }
+    }
+
+    /// `view` is world rectangle (min, max) shown in the picture of width x height pixels,
+    /// `Camera::view_bounds` for what is on the screen or `vector::data_bounds` for everything.
+    /// If aspect ratios differ the view is centered, like with `preserveAspectRatio`
+    pub fn export(&self, lines: &Lines, view: (DVec2, DVec2), width: f32, height: f32) -> String {
+        let (min, max) = view;
+        let size = (max - min).as_vec2();
+        let scale = match self.width_mode {
+            WidthMode::World => 1.,
+            // world units in one pixel
+            WidthMode::Pixels => (size.x() / width).max(size.y() / height),
+        };
+        let (join, miter_limit) = match self.join {
+            LineJoin::Miter(limit) => ("miter", limit.max(1.)),
+            LineJoin::Round => ("round", 4.),
+            LineJoin::Bevel => ("bevel", 4.),
+        };
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+            width,
+            height,
+            0.,
+            -size.y(),
+            size.x(),
+            size.y()
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<g transform="scale(1 -1)" fill="none" stroke-linejoin="{}" stroke-miterlimit="{}">"#,
+            join, miter_limit
+        )
+        .unwrap();
+        let strokes = strokes(lines.iter_with_centers(), min, scale, self.time);
+        for (i, stroke) in strokes.iter().enumerate() {
+            write_stroke(&mut svg, stroke, i, self.join);
+        }
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+}
+
+fn rgb(color: Vec4) -> String {
+    let channel = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.x()),
+        channel(color.y()),
+        channel(color.z())
+    )
+}
+
+fn cap_name(cap: LineCap) -> &'static str {
+    match cap {
+        LineCap::Butt => "butt",
+        LineCap::Square => "square",
+        LineCap::Round => "round",
+    }
+}
+
+/// `id` makes gradient names unique
+fn write_stroke(svg: &mut String, stroke: &Stroke, id: usize, join: LineJoin) {
+    if stroke.outline_width > 0. {
+        let casing = stroke.thickness + stroke.outline_width;
+        let color = stroke.outline_color;
+        write_shape(svg, stroke, casing, &rgb(color), color.w());
+        write_join(svg, stroke.end_join(casing, join), casing, color);
+    }
+    let end_join = stroke.end_join(stroke.thickness, join);
+    write_join(svg, end_join, stroke.thickness, stroke.end_color);
+    if stroke.color != stroke.end_color {
+        let (a, b) = (stroke.points[0], stroke.points[stroke.points.len() - 1]);
+        writeln!(
+            svg,
+            r#"<linearGradient id="g{}" gradientUnits="userSpaceOnUse" x1="{}" y1="{}" x2="{}" y2="{}"><stop offset="0" stop-color="{}" stop-opacity="{}"/><stop offset="1" stop-color="{}" stop-opacity="{}"/></linearGradient>"#,
+            id,
+            a.x(),
+            a.y(),
+            b.x(),
+            b.y(),
+            rgb(stroke.color),
+            stroke.color.w(),
+            rgb(stroke.end_color),
+            stroke.end_color.w()
+        )
+        .unwrap();
+        write_shape(svg, stroke, stroke.thickness, &format!("url(#g{})", id), 1.);
+    } else {
+        let color = stroke.color;
+        write_shape(svg, stroke, stroke.thickness, &rgb(color), color.w());
+    }
+}
+
+/// Join piece of half width `th`
+fn write_join(svg: &mut String, shape: Option<JoinShape>, th: f32, color: Vec4) {
+    let shape = match shape {
+        Some(shape) => shape,
+        None => return,
+    };
+    let mut d = String::new();
+    let last = shape.points.len() - 1;
+    for (i, p) in shape.points.iter().enumerate() {
+        if i == 0 {
+            write!(d, "M{} {}", p.x(), p.y()).unwrap();
+        } else if i == last && shape.round {
+            let sweep = if shape.ccw { 1 } else { 0 };
+            write!(d, " A{} {} 0 0 {} {} {}", th, th, sweep, p.x(), p.y()).unwrap();
+        } else {
+            write!(d, " L{} {}", p.x(), p.y()).unwrap();
+        }
+    }
+    write!(
+        svg,
+        r#"<path d="{} Z" fill="{}" stroke="none""#,
+        d,
+        rgb(color)
+    )
+    .unwrap();
+    if color.w() < 1. {
+        write!(svg, r#" fill-opacity="{}""#, color.w()).unwrap();
+    }
+    svg.push_str("/>\n");
+}
+
+/// Stroke of half width `th`
+fn write_shape(svg: &mut String, stroke: &Stroke, th: f32, paint: &str, opacity: f32) {
+    let shape = stroke.shape(th);
+    let mut attributes = format!(
+        r#"stroke="{}" stroke-width="{}" stroke-linecap="{}""#,
+        paint,
+        2. * th,
+        cap_name(shape.cap)
+    );
+    if opacity < 1. {
+        write!(attributes, r#" stroke-opacity="{}""#, opacity).unwrap();
+    }
+    let pattern = stroke.dash_pattern();
+    if !pattern.is_empty() {
+        let pattern: Vec<String> = pattern.iter().map(|d| d.to_string()).collect();
+        write!(
+            attributes,
+            r#" stroke-dasharray="{}" stroke-dashoffset="{}""#,
+            pattern.join(" "),
+            stroke.dash_offset
+        )
+        .unwrap();
+    }
+
+    let points = &shape.points;
+    if points.len() == 2 && !shape.closed {
+        let (a, b) = (points[0], points[1]);
+        writeln!(
+            svg,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+            a.x(),
+            a.y(),
+            b.x(),
+            b.y(),
+            attributes
+        )
+        .unwrap();
+    } else {
+        let mut d = String::new();
+        for (i, p) in points.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            write!(d, "{}{} {} ", command, p.x(), p.y()).unwrap();
+        }
+        if shape.closed {
+            d.push('Z');
+        }
+        writeln!(svg, r#"<path d="{}" {}/>"#, d.trim_end(), attributes).unwrap();
+    }
+
+    for (center, t) in shape.disks {
+        // arc from one side of the end to another through the point sticking out by th
+        let n = vec2(-t.y(), t.x()) * th;
+        let (a, b) = (center + n, center - n);
+        write!(
+            svg,
+            r#"<path d="M{} {} A{} {} 0 0 0 {} {} Z" fill="{}" stroke="none""#,
+            a.x(),
+            a.y(),
+            th,
+            th,
+            b.x(),
+            b.y(),
+            paint
+        )
+        .unwrap();
+        if opacity < 1. {
+            write!(svg, r#" fill-opacity="{}""#, opacity).unwrap();
+        }
+        svg.push_str("/>\n");
+    }
+}
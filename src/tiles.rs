@@ -0,0 +1,385 @@
+// Tiled drawing of big static datasets. The square around the data is split into zoom/x/y tiles
+// like web maps, every tile gets its own GPU instance buffer with lines of the LOD level for its zoom.
+// Tiles are built when they come into the view and the least recently used ones are evicted,
+// so GPU memory depends on the screen size, not on the dataset size.
+// CPU memory does depend on it: the dataset is kept as LodPyramid points (see its memory)
+// and every level which was drawn gets a spatial index of about 44 bytes per line.
+//
+// Every line goes to one tile, the one with its center, so nothing is drawn twice.
+// Lines sticking out of their tile by more than a half of the tile size go to the tiles of
+// lower zoom where they fit (loose quadtree), those tiles are drawn together with the visible ones.
+
+use std::collections::HashMap;
+
+use miniquad::*;
+
+use crate::camera::Camera;
+use crate::draw_lines::{GpuLine, LineJoin, WidthMode};
+use crate::dvec2::{dvec2, DVec2};
+use crate::lod::LodPyramid;
+use crate::pick::pick_from;
+use crate::raster::Raster;
+use crate::spatial::{half_size, join_extent, SpatialIndex};
+
+/// Deeper tiles are smaller than f32 rounding of Web Mercator meters anyway
+pub const MAX_TILE_ZOOM: u8 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileId {
+    pub zoom: u8,
+    /// from the left of the data square
+    pub x: u32,
+    /// from the bottom of the data square, world y goes up
+    pub y: u32,
+}
+
+/// Content of a tile: lines of the LOD `level` with centers in the tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub id: TileId,
+    pub level: usize,
+    /// Only lines too long for tiles of the next zoom. Tiles of the view zoom have all lines
+    /// which fit, tiles of the lower zooms are drawn only for the long ones
+    pub long_only: bool,
+}
+
+struct Tile {
+    /// None for empty tile
+    bindings: Option<Bindings>,
+    /// world position which tile lines are relative to on GPU
+    origin: DVec2,
+    lines_num: usize,
+    /// frame when the tile was drawn the last time
+    last_used: u64,
+}
+
+/// Static dataset split into tiles, drawn by LinesRenderer (see `LinesRenderer::set_tiles`).
+/// GPU buffers are up to `max_cached_lines` * 128 bytes, they are deleted when it's dropped
+pub struct TileSet {
+    lod: LodPyramid,
+    /// built on the first use of the level
+    indices: Vec<Option<SpatialIndex>>,
+    width_mode: WidthMode,
+    join: LineJoin,
+    /// the bottom left corner and the side of the square with all lines, tile 0/0/0
+    origin: DVec2,
+    size: f64,
+    tiles: HashMap<TileKey, Tile>,
+    frame: u64,
+    cached_lines: usize,
+    /// Tiles are from a half of it to it pixels on the screen
+    pub tile_pixels: f32,
+    /// Tiles which are not in the view are evicted when there are more lines than that
+    pub max_cached_lines: usize,
+    /// Limit for number of tiles, empty ones are counted too
+    pub max_cached_tiles: usize,
+}
+
+impl TileSet {
+    pub fn new(lod: LodPyramid) -> Self {
+        let (min, max) = lod.bounds().unwrap_or((DVec2::zero(), dvec2(1., 1.)));
+        let size = (max.x - min.x).max(max.y - min.y).max(f64::MIN_POSITIVE);
+        let indices = (0..lod.levels().len()).map(|_| None).collect();
+        TileSet {
+            lod,
+            indices,
+            width_mode: WidthMode::default(),
+            join: LineJoin::default(),
+            origin: min,
+            size,
+            tiles: HashMap::new(),
+            frame: 0,
+            cached_lines: 0,
+            tile_pixels: 512.,
+            max_cached_lines: 2_000_000,
+            max_cached_tiles: 4096,
+        }
+    }
+
+    pub fn lod(&self) -> &LodPyramid {
+        &self.lod
+    }
+
+    pub fn cached_tiles(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Lines in GPU buffers of cached tiles
+    pub fn cached_lines(&self) -> usize {
+        self.cached_lines
+    }
+
+    /// Tiles depend on the width mode, they are built again when it changes
+    pub fn set_width_mode(&mut self, width_mode: WidthMode) {
+        if width_mode != self.width_mode {
+            self.clear();
+            self.indices.iter_mut().for_each(|index| *index = None);
+            self.width_mode = width_mode;
+        }
+    }
+
+    /// Tiles depend on how far joins stick out too, they are built again when it changes
+    pub fn set_join(&mut self, join: LineJoin) {
+        if join_extent(join) != join_extent(self.join) {
+            self.clear();
+            self.indices.iter_mut().for_each(|index| *index = None);
+        }
+        self.join = join;
+    }
+
+    /// Deletes all tiles, they are built again when needed
+    pub fn clear(&mut self) {
+        for tile in self.tiles.values() {
+            delete_tile(tile);
+        }
+        self.tiles.clear();
+        self.cached_lines = 0;
+    }
+
+    fn tile_size(&self, zoom: u8) -> f64 {
+        self.size / 2f64.powi(zoom as i32)
+    }
+
+    /// Tile zoom for camera on the screen of `width` pixels
+    pub fn zoom_for(&self, camera: &Camera, width: f32) -> u8 {
+        let pixel = 1. / (camera.zoom as f64 * width as f64);
+        let tiles = self.size / (self.tile_pixels as f64 * pixel);
+        (tiles.log2().ceil().max(0.) as u8).min(MAX_TILE_ZOOM)
+    }
+
+    /// Tiles to draw for the camera view, `tolerance_px` is for LOD level choice.
+    /// Tiles of the view zoom are followed by tiles with long lines of lower zooms
+    pub fn visible_tiles(
+        &mut self,
+        camera: &Camera,
+        width: f32,
+        height: f32,
+        tolerance_px: f32,
+    ) -> Vec<TileKey> {
+        let zoom = self.zoom_for(camera, width);
+        let tile_size = self.tile_size(zoom);
+        // tile has tile_pixels pixels at most, so its pixels are at least as big as the screen ones
+        let level = self
+            .lod
+            .level_for_tolerance(tolerance_px as f64 * tile_size / self.tile_pixels as f64);
+        let (min, max) = self.index(level).view_bounds(camera, width, height);
+        let mut keys = vec![];
+        for tiles_zoom in (0..=zoom).rev() {
+            let tile_size = self.tile_size(tiles_zoom);
+            let tiles_num = 1u64 << tiles_zoom;
+            // lines of the tiles reach out by a half of the tile size
+            let reach = tile_size / 2.;
+            let tile = |value: f64, origin: f64| ((value - origin) / tile_size).floor();
+            let (x0, x1) = (
+                tile(min.x - reach, self.origin.x),
+                tile(max.x + reach, self.origin.x),
+            );
+            let (y0, y1) = (
+                tile(min.y - reach, self.origin.y),
+                tile(max.y + reach, self.origin.y),
+            );
+            let last = (tiles_num - 1) as f64;
+            if x1 < 0. || y1 < 0. || x0 > last || y0 > last {
+                // the view is out of the data
+                continue;
+            }
+            let clamp = |tile: f64| tile.clamp(0., last) as u32;
+            let (x0, x1, y0, y1) = (clamp(x0), clamp(x1), clamp(y0), clamp(y1));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    keys.push(TileKey {
+                        id: TileId {
+                            zoom: tiles_zoom,
+                            x,
+                            y,
+                        },
+                        level,
+                        long_only: tiles_zoom < zoom,
+                    });
+                }
+            }
+        }
+        keys
+    }
+
+    fn index(&mut self, level: usize) -> &SpatialIndex {
+        let lod = &self.lod;
+        let (width_mode, join) = (self.width_mode, self.join);
+        self.indices[level]
+            .get_or_insert_with(|| SpatialIndex::from_lines(lod.lines(level), width_mode, join))
+    }
+
+    /// Lines of the tile, indices of `lod().line` in the `key.level`, in ascending order
+    pub fn tile_lines(&mut self, key: TileKey) -> Vec<usize> {
+        let tile_size = self.tile_size(key.id.zoom);
+        let min = self.origin + dvec2(key.id.x as f64, key.id.y as f64) * tile_size;
+        let max = min + dvec2(tile_size, tile_size);
+        // line with the center in the tile intersects it
+        let candidates = self.index(key.level).query(min, max);
+        let last = (1u64 << key.id.zoom) - 1;
+        let (width_mode, join_extent) = (self.width_mode, join_extent(self.join));
+        candidates
+            .into_iter()
+            .filter(|i| {
+                let (line, center) = self.lod.line(key.level, *i);
+                // the last tiles take lines on their far edge too
+                let inside = |value: f64, min: f64, max: f64, tile: u32| {
+                    min <= value && (value < max || tile as u64 == last)
+                };
+                if !inside(center.x, min.x, max.x, key.id.x)
+                    || !inside(center.y, min.y, max.y, key.id.y)
+                {
+                    return false;
+                }
+                let half = half_size(&line, width_mode, join_extent);
+                let reach = half.x.max(half.y);
+                let fits = |zoom: u8| reach <= self.tile_size(zoom) / 2.;
+                // tile 0/0/0 takes lines which don't fit anywhere
+                (key.id.zoom == 0 || fits(key.id.zoom))
+                    && (!key.long_only || !fits(key.id.zoom + 1))
+            })
+            .collect()
+    }
+
+    /// Lines within `tolerance_px` pixels of `world_point` as they are drawn by the camera
+    /// on the screen of width x height pixels, the closest first (see `pick::pick`).
+    /// Returns indices of `lod().line` in level 0: the original lines are searched,
+    /// zoomed out tiles show simplified ones up to `LinesRenderer::lod_tolerance` pixels away.
+    /// `raster` has the renderer settings, see `Raster::from_renderer`
+    pub fn pick<P: Into<DVec2>>(
+        &mut self,
+        raster: &Raster,
+        camera: &Camera,
+        width: f32,
+        height: f32,
+        world_point: P,
+        tolerance_px: f32,
+    ) -> Vec<usize> {
+        let point = world_point.into();
+        self.pick_hits(raster, camera, width, height, point, tolerance_px)
+            .into_iter()
+            .map(|(_, i)| i)
+            .collect()
+    }
+
+    /// `pick` with distances in world units
+    pub(crate) fn pick_hits(
+        &mut self,
+        raster: &Raster,
+        camera: &Camera,
+        width: f32,
+        height: f32,
+        point: DVec2,
+        tolerance_px: f32,
+    ) -> Vec<(f32, usize)> {
+        self.set_width_mode(raster.width_mode);
+        self.set_join(raster.join);
+        let candidates = self.index(0).near(point, camera, width, tolerance_px);
+        let lod = &self.lod;
+        pick_from(
+            |i| lod.line(0, i),
+            candidates,
+            raster,
+            camera,
+            width,
+            height,
+            point,
+            tolerance_px,
+        )
+    }
+
+    /// Builds missing tiles of the view, evicts old ones and returns (bindings, origin, instances)
+    /// for every non empty tile to draw
+    pub(crate) fn prepare(
+        &mut self,
+        ctx: &mut Context,
+        camera: &Camera,
+        width: f32,
+        height: f32,
+        tolerance_px: f32,
+        geometry: (Buffer, Buffer),
+    ) -> Vec<(Bindings, DVec2, usize)> {
+        self.frame += 1;
+        let mut draws = vec![];
+        for key in self.visible_tiles(camera, width, height, tolerance_px) {
+            if !self.tiles.contains_key(&key) {
+                let tile = self.build(ctx, key, geometry);
+                self.cached_lines += tile.lines_num;
+                self.tiles.insert(key, tile);
+            }
+            let tile = self.tiles.get_mut(&key).unwrap();
+            tile.last_used = self.frame;
+            if let Some(bindings) = &tile.bindings {
+                draws.push((bindings.clone(), tile.origin, tile.lines_num));
+            }
+        }
+        self.evict();
+        draws
+    }
+
+    fn build(&mut self, ctx: &mut Context, key: TileKey, geometry: (Buffer, Buffer)) -> Tile {
+        let indices = self.tile_lines(key);
+        let tile_size = self.tile_size(key.id.zoom);
+        let origin = self.origin + dvec2(key.id.x as f64 + 0.5, key.id.y as f64 + 0.5) * tile_size;
+        let instances: Vec<GpuLine> = indices
+            .iter()
+            .map(|i| {
+                let (line, center) = self.lod.line(key.level, *i);
+                GpuLine::new(&line, (center - origin).as_vec2())
+            })
+            .collect();
+        let bindings = if instances.is_empty() {
+            None
+        } else {
+            let buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &instances);
+            Some(Bindings {
+                vertex_buffers: vec![geometry.0, buffer],
+                index_buffer: geometry.1,
+                images: vec![],
+            })
+        };
+        Tile {
+            bindings,
+            origin,
+            lines_num: instances.len(),
+            last_used: self.frame,
+        }
+    }
+
+    /// Removes the least recently used tiles over the limits, tiles of the current frame are kept
+    fn evict(&mut self) {
+        if self.cached_lines <= self.max_cached_lines && self.tiles.len() <= self.max_cached_tiles {
+            return;
+        }
+        let mut old: Vec<(u64, TileKey)> = self
+            .tiles
+            .iter()
+            .filter(|(_, tile)| tile.last_used < self.frame)
+            .map(|(key, tile)| (tile.last_used, *key))
+            .collect();
+        old.sort_unstable_by_key(|(last_used, _)| *last_used);
+        for (_, key) in old {
+            if self.cached_lines <= self.max_cached_lines
+                && self.tiles.len() <= self.max_cached_tiles
+            {
+                break;
+            }
+            let tile = self.tiles.remove(&key).unwrap();
+            self.cached_lines -= tile.lines_num;
+            delete_tile(&tile);
+        }
+    }
+}
+
+impl Drop for TileSet {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+fn delete_tile(tile: &Tile) {
+    if let Some(bindings) = &tile.bindings {
+        // geometry buffers are shared by all tiles
+        bindings.vertex_buffers[1].delete();
+    }
+}
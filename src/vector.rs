@@ -0,0 +1,299 @@
+// Lines as vector strokes for exporters.
+// Segments of a polyline with the same style are merged back into one path, so joins are drawn
+// by the format itself. Segments which can't be merged (gradients, style changes) get butt ends
+// and a filled join piece, like the halves of the join hex_shader draws on both sides of the bisector.
+// Caps removed by SegmentType become butt, like discards in hex_shader.
+
+use glam::{Vec2, Vec4};
+
+use crate::draw_lines::{Line, LineCap, LineJoin, Lines, WidthMode};
+use crate::dvec2::{dvec2, DVec2};
+
+/// Bounds (min, max) of lines with their thickness and outline, in world units.
+/// For WidthMode::Pixels thickness is ignored. None if there are no lines
+pub fn data_bounds(lines: &Lines, width_mode: WidthMode) -> Option<(DVec2, DVec2)> {
+    lines
+        .iter_with_centers()
+        .fold(None, |bounds, (line, center)| {
+            let extent = match width_mode {
+                WidthMode::World => (line.thickness + line.outline_width) as f64,
+                WidthMode::Pixels => 0.,
+            };
+            let half = dvec2(
+                (line.dir.x().abs() / 2.) as f64 + extent,
+                (line.dir.y().abs() / 2.) as f64 + extent,
+            );
+            let (min, max) = (center - half, center + half);
+            match bounds {
+                None => Some((min, max)),
+                Some((bmin, bmax)) => Some((bmin.min(min), bmax.max(max))),
+            }
+        })
+}
+
+/// Polyline with one style, all sizes are in world units
+#[derive(Debug, Clone)]
+pub(crate) struct Stroke {
+    pub points: Vec<Vec2>,
+    pub closed: bool,
+    /// half of the stroke width
+    pub thickness: f32,
+    pub outline_width: f32,
+    pub color: Vec4,
+    /// differs from color only for single segments, the color goes from the first point to the last one
+    pub end_color: Vec4,
+    pub outline_color: Vec4,
+    pub start_cap: LineCap,
+    pub end_cap: LineCap,
+    /// dash pattern (dash, gap, dash, gap), zeros for solid line
+    pub dash: Vec4,
+    /// position in dash pattern at the first point
+    pub dash_offset: f32,
+    /// direction of the next segment which isn't merged into the stroke, zero if there is no one
+    pub next_dir: Vec2,
+}
+
+impl Stroke {
+    pub fn is_dashed(&self) -> bool {
+        self.dash.x() + self.dash.y() + self.dash.z() + self.dash.w() > 0.
+    }
+
+    /// dash pattern as drawn by hex_shader, the second dash is skipped if it's empty.
+    /// Empty for solid line
+    pub fn dash_pattern(&self) -> Vec<f32> {
+        let d = self.dash;
+        if !self.is_dashed() {
+            vec![]
+        } else if d.z() > 0. {
+            vec![d.x(), d.y(), d.z(), d.w()]
+        } else {
+            vec![d.x(), d.y() + d.w()]
+        }
+    }
+
+    /// Join between the stroke end and the next segment for half width `th` in `end_color`,
+    /// None if the end isn't joined, the corner is straight or the end is in a dash gap
+    pub fn end_join(&self, th: f32, join: LineJoin) -> Option<JoinShape> {
+        if !is_joined(self.next_dir) || self.closed {
+            return None;
+        }
+        let last = self.points.len() - 1;
+        let e = self.points[last];
+        let t = (e - self.points[last - 1]).normalize();
+        let w = self.next_dir.normalize();
+        let m = t - w;
+        if m.dot(m) < 0.0001 || !self.in_dash(self.length()) {
+            return None;
+        }
+        // the outer side of the corner
+        let side = if t.perp_dot(w) > 0. { -1. } else { 1. };
+        let (n1, n2) = (perp(t) * side, perp(w) * side);
+        let (p1, p2) = (e + n1 * th, e + n2 * th);
+        // cos of the corner between the normals, the miter is 1 / sin of its half long
+        let cos = n1.dot(n2);
+        let points = match join {
+            LineJoin::Miter(limit) if (1. + cos) * 0.5 * limit * limit >= 1. => {
+                vec![e, p1, e + (n1 + n2) * (th / (1. + cos)), p2]
+            }
+            _ => vec![e, p1, p2],
+        };
+        Some(JoinShape {
+            points,
+            round: join == LineJoin::Round,
+            ccw: side < 0.,
+        })
+    }
+
+    fn length(&self) -> f32 {
+        self.points.windows(2).map(|w| (w[1] - w[0]).length()).sum()
+    }
+
+    /// the point at `distance` from the start is in a dash, always for solid stroke
+    fn in_dash(&self, distance: f32) -> bool {
+        let pattern = self.dash_pattern();
+        let total: f32 = pattern.iter().sum();
+        if pattern.is_empty() || total <= 0. {
+            return true;
+        }
+        let mut q = (self.dash_offset + distance).rem_euclid(total);
+        for dash in pattern.chunks(2) {
+            if q <= dash[0] {
+                return true;
+            }
+            q -= dash[0] + dash[1];
+        }
+        false
+    }
+
+    /// Geometry of the stroke (or its casing) with half width `th` for formats
+    /// which have one cap for both ends of a path.
+    /// Different caps are made of butt ends, extended ones for square caps and half disks for round
+    pub fn shape(&self, th: f32) -> Shape {
+        let mut shape = Shape {
+            points: self.points.clone(),
+            closed: self.closed,
+            cap: self.start_cap,
+            disks: vec![],
+        };
+        if self.start_cap == self.end_cap || self.closed || self.is_dashed() {
+            return shape;
+        }
+        shape.cap = LineCap::Butt;
+        let last = self.points.len() - 1;
+        let ends = [
+            (0, self.points[0] - self.points[1], self.start_cap),
+            (
+                last,
+                self.points[last] - self.points[last - 1],
+                self.end_cap,
+            ),
+        ];
+        for (i, out, cap) in ends.iter() {
+            let t = out.normalize();
+            match cap {
+                LineCap::Butt => {}
+                LineCap::Square => shape.points[*i] += t * th,
+                LineCap::Round => shape.disks.push((self.points[*i], t)),
+            }
+        }
+        shape
+    }
+}
+
+/// Filled join piece outside of the butt ends of two segments
+pub(crate) struct JoinShape {
+    /// the vertex, the outer corner of the first segment end, the miter tip if there is one
+    /// and the outer corner of the second segment start
+    pub points: Vec<Vec2>,
+    /// the last two points are connected by an arc around the vertex
+    pub round: bool,
+    /// the arc goes counterclockwise
+    pub ccw: bool,
+}
+
+pub(crate) struct Shape {
+    pub points: Vec<Vec2>,
+    pub closed: bool,
+    pub cap: LineCap,
+    /// (center, outward direction) of half disks at round ends
+    pub disks: Vec<(Vec2, Vec2)>,
+}
+
+fn cap(value: f32) -> LineCap {
+    match value.round() as u8 {
+        0 => LineCap::Butt,
+        1 => LineCap::Square,
+        _ => LineCap::Round,
+    }
+}
+
+fn start(line: &Line) -> Vec2 {
+    line.position - line.dir / 2.
+}
+
+fn end(line: &Line) -> Vec2 {
+    line.position + line.dir / 2.
+}
+
+/// equal up to f32 rounding in position +- dir / 2
+fn same_point(a: Vec2, b: Vec2) -> bool {
+    (a - b).length() <= 1e-5 * a.length().max(b.length())
+}
+
+fn is_joined(dir: Vec2) -> bool {
+    dir.dot(dir) > 0.
+}
+
+fn perp(v: Vec2) -> Vec2 {
+    Vec2::new(-v.y(), v.x())
+}
+
+/// Cap at the segment start as drawn by hex_shader. Joined ends which aren't merged with
+/// their neighbour are butt, the join piece is drawn by the previous stroke (see `Stroke::end_join`),
+/// so translucent lines don't get overlaps at vertices
+fn start_cap(line: &Line) -> LineCap {
+    let st = line.segment_type;
+    if is_joined(line.prev_dir) || (st - 1.).abs() < 0.01 || (st - 3.).abs() < 0.01 {
+        LineCap::Butt
+    } else {
+        cap(line.caps.x())
+    }
+}
+
+fn end_cap(line: &Line) -> LineCap {
+    let st = line.segment_type;
+    if is_joined(line.next_dir) || (st - 2.).abs() < 0.01 || (st - 3.).abs() < 0.01 {
+        LineCap::Butt
+    } else {
+        cap(line.caps.y())
+    }
+}
+
+fn is_gradient(line: &Line) -> bool {
+    line.color != line.end_color
+}
+
+/// next continues the polyline of prev and can be drawn within the same path
+fn continues(prev: &Line, next: &Line) -> bool {
+    is_joined(prev.next_dir)
+        && is_joined(next.prev_dir)
+        && !is_gradient(prev)
+        && !is_gradient(next)
+        && same_point(end(prev), start(next))
+        && prev.thickness == next.thickness
+        && prev.color == next.color
+        && prev.caps == next.caps
+        && prev.dash == next.dash
+        && prev.dash_offset == next.dash_offset
+        && prev.flow_speed == next.flow_speed
+        && prev.outline_width == next.outline_width
+        && prev.outline_color == next.outline_color
+}
+
+/// Strokes of (line, f64 position) pairs with points relative to `origin`, they are rebased in f64,
+/// so big world coordinates don't lose precision.
+/// `scale` is world units in one unit of thickness (see WidthMode), `time` is for animated dashes
+pub(crate) fn strokes<'a, I: IntoIterator<Item = (&'a Line, DVec2)>>(
+    lines: I,
+    origin: DVec2,
+    scale: f32,
+    time: f32,
+) -> Vec<Stroke> {
+    let mut strokes: Vec<Stroke> = vec![];
+    let mut last: Option<Line> = None;
+    for (line, center) in lines {
+        if line.dir.dot(line.dir) == 0. {
+            continue;
+        }
+        let mut line = *line;
+        line.position = (center - origin).as_vec2();
+        let line = &line;
+        match (&last, strokes.last_mut()) {
+            (Some(prev), Some(stroke)) if !stroke.closed && continues(prev, line) => {
+                if is_joined(line.next_dir) && same_point(end(line), stroke.points[0]) {
+                    stroke.closed = true;
+                } else {
+                    stroke.points.push(end(line));
+                }
+                stroke.end_cap = end_cap(line);
+                stroke.next_dir = line.next_dir;
+            }
+            _ => strokes.push(Stroke {
+                points: vec![start(line), end(line)],
+                closed: false,
+                thickness: line.thickness * scale,
+                outline_width: line.outline_width * scale,
+                color: line.color,
+                end_color: line.end_color,
+                outline_color: line.outline_color,
+                start_cap: start_cap(line),
+                end_cap: end_cap(line),
+                dash: line.dash * scale,
+                dash_offset: line.distance + (line.dash_offset - line.flow_speed * time) * scale,
+                next_dir: line.next_dir,
+            }),
+        }
+        last = Some(*line);
+    }
+    strokes
+}
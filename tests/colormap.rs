@@ -0,0 +1,93 @@
+use glam::{vec4, Vec4};
+use lines::colormap::{categorical, data_range, normalize, rgb, Colormap, CATEGORY10};
+
+/// Largest channel difference
+fn difference(a: Vec4, b: Vec4) -> f32 {
+    (a - b).abs().max_element()
+}
+
+#[test]
+fn normalize_clamps() {
+    assert_eq!(normalize(5., 0., 10.), 0.5);
+    assert_eq!(normalize(-5., 0., 10.), 0.);
+    assert_eq!(normalize(15., 0., 10.), 1.);
+    assert_eq!(normalize(-2., -4., 0.), 0.5);
+    // zero width range is the middle of the colormap, not NaN
+    assert_eq!(normalize(3., 3., 3.), 0.5);
+    assert_eq!(normalize(100., 3., 3.), 0.5);
+}
+
+#[test]
+fn normalize_tiny_and_reversed_ranges() {
+    // the range is compared exactly, tiny values still spread over the colormap
+    assert_eq!(normalize(1e-8, 1e-8, 2e-8), 0.);
+    assert_eq!(normalize(2e-8, 1e-8, 2e-8), 1.);
+    assert!((normalize(1.5e-8, 1e-8, 2e-8) - 0.5).abs() < 1e-6);
+    // min above max flips the map
+    assert_eq!(normalize(10., 10., 0.), 0.);
+    assert_eq!(normalize(0., 10., 0.), 1.);
+    assert_eq!(normalize(2.5, 10., 0.), 0.75);
+    assert_eq!(normalize(-5., 10., 0.), 1.);
+    assert_eq!(normalize(15., 10., 0.), 0.);
+}
+
+#[test]
+fn data_range_ignores_nans() {
+    assert_eq!(data_range(&[3., -1., f32::NAN, 7., 2.]), Some((-1., 7.)));
+    assert_eq!(data_range(&[4.]), Some((4., 4.)));
+    assert_eq!(data_range(&[f32::NAN, f32::NAN]), None);
+    assert_eq!(data_range(&[]), None);
+}
+
+/// Polynomial fits are close to the reference tables, Turbo one is rougher
+fn fit_tolerance(colormap: Colormap) -> f32 {
+    match colormap {
+        Colormap::Turbo => 0.1,
+        _ => 0.02,
+    }
+}
+
+#[test]
+fn colormap_endpoints() {
+    // (colormap, t, reference value from matplotlib and Google Turbo tables)
+    let cases = [
+        (Colormap::Viridis, 0., [0.267004, 0.004874, 0.329415]),
+        (Colormap::Viridis, 0.5, [0.127568, 0.566949, 0.550556]),
+        (Colormap::Viridis, 1., [0.993248, 0.906157, 0.143936]),
+        (Colormap::Magma, 0., [0.001462, 0.000466, 0.013866]),
+        (Colormap::Magma, 1., [0.987053, 0.991438, 0.749504]),
+        (Colormap::Turbo, 0., [0.18995, 0.07176, 0.23217]),
+        (Colormap::Turbo, 0.5, [0.64362, 0.98999, 0.23356]),
+        (Colormap::Turbo, 1., [0.4796, 0.01583, 0.01055]),
+    ];
+    for (colormap, t, [r, g, b]) in cases.iter() {
+        let color = colormap.color(*t);
+        let expected = vec4(*r, *g, *b, 1.);
+        assert!(
+            difference(color, expected) <= fit_tolerance(*colormap),
+            "{:?}({}) = {:?}, expected {:?}",
+            colormap,
+            t,
+            color,
+            expected
+        );
+    }
+    // out of range values are clamped
+    let viridis = Colormap::Viridis;
+    assert_eq!(viridis.color(-1.), viridis.color(0.));
+    assert_eq!(viridis.color(2.), viridis.color(1.));
+    let scaled = viridis.scaled(10., 20.);
+    assert_eq!(scaled(10.), viridis.color(0.));
+    assert_eq!(scaled(20.), viridis.color(1.));
+}
+
+#[test]
+fn palettes_repeat() {
+    let first = vec4(31. / 255., 119. / 255., 180. / 255., 1.);
+    assert_eq!(categorical(CATEGORY10, 0), first);
+    assert_eq!(categorical(CATEGORY10, 10), categorical(CATEGORY10, 0));
+    assert_eq!(
+        categorical(CATEGORY10, 9),
+        rgb(*CATEGORY10.last().unwrap()).extend(1.)
+    );
+}
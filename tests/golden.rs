@@ -0,0 +1,327 @@
+// Golden image tests: known scenes are drawn with the CPU copy of hex_shader (lines::raster)
+// and compared with reference pictures from tests/golden.
+// After an intended change of the picture run `UPDATE_GOLDEN=1 cargo test --test golden`
+// and look at the new references before committing them.
+
+use std::{fs::File, path::PathBuf};
+
+use glam::{vec2, vec4, Vec2};
+use lines::{
+    camera::Camera,
+    colormap::Colormap,
+    draw_lines::{Line, LineCap, LineJoin, LineStyle, Lines, SegmentType, WidthMode},
+    dvec2::{dvec2, DVec2},
+    raster::{Image, Raster},
+};
+use quad_rand as qrand;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+/// per channel difference which is not counted as a difference (rounding, float math on other platforms)
+const CHANNEL_TOLERANCE: u8 = 3;
+/// share of pixels which may differ more than CHANNEL_TOLERANCE
+const PIXEL_TOLERANCE: f32 = 0.002;
+
+fn camera(position: Vec2, zoom: f32) -> Camera {
+    let mut camera = Camera::new(zoom, zoom);
+    camera.position2d = position.into();
+    camera.desired_position = position.into();
+    camera
+}
+
+fn render(lines: &Lines, camera: &Camera, raster: Raster) -> Image {
+    let mut image = Image::new(WIDTH, HEIGHT, vec4(1., 1., 1., 1.));
+    raster.draw(&mut image, lines, camera);
+    image
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+fn read_png(path: &PathBuf) -> Image {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let (info, mut reader) = decoder.read_info().unwrap();
+    assert_eq!(info.color_type, png::ColorType::RGBA);
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    Image {
+        width: info.width,
+        height: info.height,
+        data,
+    }
+}
+
+fn write_png(path: &PathBuf, image: &Image) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    image.save_png(path).unwrap();
+}
+
+/// Faded expected picture with differing pixels in red, brighter for bigger difference
+fn diff_image(expected: &Image, actual: &Image) -> (Image, usize) {
+    let mut diff = expected.clone();
+    let mut differs = 0;
+    for ((e, a), d) in expected
+        .data
+        .chunks(4)
+        .zip(actual.data.chunks(4))
+        .zip(diff.data.chunks_mut(4))
+    {
+        let max = e
+            .iter()
+            .zip(a.iter())
+            .map(|(e, a)| (*e as i16 - *a as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap();
+        if max > CHANNEL_TOLERANCE {
+            differs += 1;
+            d.copy_from_slice(&[128 + max / 2, 0, 0, 255]);
+        } else {
+            let grey = (e[0] as u16 + e[1] as u16 + e[2] as u16) / 3 / 4 + 191;
+            d.copy_from_slice(&[grey as u8, grey as u8, grey as u8, 255]);
+        }
+    }
+    (diff, differs)
+}
+
+fn check(name: &str, actual: Image) {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&path, &actual);
+        return;
+    }
+    assert!(
+        path.exists(),
+        "no reference image {:?}, run with UPDATE_GOLDEN=1 to create it",
+        path
+    );
+    let expected = read_png(&path);
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height),
+        "{}: size of the picture changed",
+        name
+    );
+
+    let (diff, differs) = diff_image(&expected, &actual);
+    let allowed = (PIXEL_TOLERANCE * (WIDTH * HEIGHT) as f32) as usize;
+    if differs > allowed {
+        let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        let actual_path = out.join(format!("{}.actual.png", name));
+        let diff_path = out.join(format!("{}.diff.png", name));
+        write_png(&actual_path, &actual);
+        write_png(&diff_path, &diff);
+        panic!(
+            "{}: {} pixels differ from the reference (allowed {}), see {:?} and {:?}",
+            name, differs, allowed, actual_path, diff_path
+        );
+    }
+}
+
+/// random polyline around `center`
+fn random_lines(center: DVec2) -> Lines {
+    qrand::srand(7);
+    let mut points = vec![];
+    let mut values = vec![];
+    for i in 0..40 {
+        let offset = vec2(qrand::gen_range(-100., 100.), qrand::gen_range(-75., 75.));
+        points.push(center + offset.into());
+        values.push(i as f32);
+    }
+    let mut lines = Lines::default();
+    lines.add_colormapped_polyline(
+        &points,
+        &values,
+        Colormap::Viridis.scaled(0., 40.),
+        LineStyle::new(1.5, vec4(0., 0., 0., 1.)),
+    );
+    lines
+}
+
+#[test]
+fn random_polyline() {
+    let lines = random_lines(DVec2::zero());
+    check(
+        "random_polyline",
+        render(&lines, &camera(Vec2::zero(), 1. / 220.), Raster::default()),
+    );
+}
+
+#[test]
+fn far_from_origin() {
+    // f32 has 2 unit steps there, lines and camera are rebased in f64 and look the same
+    let center = dvec2(3e7, -2e7);
+    let lines = random_lines(center);
+    let mut camera = camera(Vec2::zero(), 1. / 220.);
+    camera.position2d = center;
+    check(
+        "random_polyline",
+        render(&lines, &camera, Raster::default()),
+    );
+}
+
+#[test]
+fn joins() {
+    let zigzag = [
+        vec2(-60., -30.),
+        vec2(-35., 30.),
+        vec2(-10., -30.),
+        vec2(0., 25.),
+        vec2(10., -30.),
+        vec2(50., 0.),
+        vec2(60., 30.),
+    ];
+    let mut lines = Lines::default();
+    lines.add_polyline(&zigzag, LineStyle::new(6., vec4(0.2, 0.4, 0.9, 0.7)));
+    lines.add_polygon(
+        &[vec2(-20., 45.), vec2(20., 45.), vec2(0., 55.)],
+        LineStyle::new(2., vec4(0.9, 0.3, 0.1, 1.)),
+    );
+    let camera = camera(vec2(0., 10.), 1. / 150.);
+    for (name, join) in [
+        ("joins_miter", LineJoin::Miter(4.)),
+        ("joins_round", LineJoin::Round),
+        ("joins_bevel", LineJoin::Bevel),
+    ]
+    .iter()
+    {
+        let raster = Raster {
+            join: *join,
+            ..Default::default()
+        };
+        check(name, render(&lines, &camera, raster));
+    }
+
+    // no antialiasing, every pixel is either covered or not
+    let raster = Raster {
+        aa_width: 0.,
+        ..Default::default()
+    };
+    check("joins_crisp", render(&lines, &camera, raster));
+}
+
+#[test]
+fn thin_lines_at_extreme_zoom() {
+    // zoomed in close to MAX_ZOOM
+    let center = vec2(1e-5, -1e-5);
+    let mut lines = Lines::default();
+    for i in 0..5 {
+        let offset = vec2(0., (i as f32 - 2.) * 2e-6);
+        lines.add_polyline(
+            &[
+                center + offset - vec2(5e-6, 0.),
+                center + offset + vec2(5e-6, 1e-6),
+            ],
+            LineStyle::new(1e-7 * (i + 1) as f32, vec4(0., 0., 0., 1.)),
+        );
+    }
+    check(
+        "thin_lines_zoom_in",
+        render(&lines, &camera(center, 80000.), Raster::default()),
+    );
+
+    // zoomed out to MIN_ZOOM, lines are thinner than a pixel
+    let mut lines = Lines::default();
+    for i in 0..10 {
+        let x = (i as f32 - 4.5) * 80.;
+        lines.add_polyline(
+            &[vec2(x, -300.), vec2(x + 40., 300.)],
+            LineStyle::new(0.2 * (i + 1) as f32, vec4(0., 0., 0., 1.)),
+        );
+    }
+    check(
+        "thin_lines_zoom_out",
+        render(&lines, &camera(Vec2::zero(), 0.001), Raster::default()),
+    );
+
+    // width in pixels doesn't depend on zoom
+    let raster = Raster {
+        width_mode: WidthMode::Pixels,
+        ..Default::default()
+    };
+    check(
+        "thin_lines_pixels",
+        render(&lines, &camera(Vec2::zero(), 0.001), raster),
+    );
+}
+
+#[test]
+fn segment_type_discards() {
+    let mut lines = Lines::default();
+    let types = [
+        SegmentType::All,
+        SegmentType::NoFirst,
+        SegmentType::NoSecond,
+        SegmentType::NoAll,
+    ];
+    for (i, segment_type) in types.iter().enumerate() {
+        let y = 40. - i as f32 * 25.;
+        let line = Line::new(
+            *segment_type,
+            vec2(-40., y),
+            vec2(40., y),
+            6.,
+            vec4(0.8, 0.1, 0.3, 1.),
+        );
+        lines.add(line);
+        // caps of any kind are discarded
+        let line = Line::new(
+            *segment_type,
+            vec2(-40., y - 10.),
+            vec2(40., y - 10.),
+            2.,
+            vec4(0.1, 0.3, 0.8, 1.),
+        )
+        .with_caps(LineCap::Square, LineCap::Square);
+        lines.add(line);
+    }
+    check(
+        "segment_type_discards",
+        render(&lines, &camera(vec2(0., 0.), 1. / 120.), Raster::default()),
+    );
+}
+
+#[test]
+fn dash_caps_across_period() {
+    let mut lines = Lines::default();
+    let color = vec4(0.2, 0.5, 0.2, 1.);
+    // caps of the last dash stick out over the period end into the next dash
+    let styles = [
+        LineStyle::new(4., color).with_dash(&[12., 3.], 0.),
+        LineStyle::new(4., color)
+            .with_cap(LineCap::Square)
+            .with_dash(&[10., 6., 4., 2.], 0.),
+        // two dashes of different lengths
+        LineStyle::new(3., color).with_dash(&[8., 7., 2., 8.], 0.),
+    ];
+    for (i, style) in styles.iter().enumerate() {
+        let y = 30. - i as f32 * 30.;
+        lines.add_polyline(&[vec2(-60., y), vec2(60., y)], *style);
+    }
+    check(
+        "dash_caps_across_period",
+        render(&lines, &camera(vec2(0., 0.), 1. / 160.), Raster::default()),
+    );
+}
+
+#[test]
+fn translucent_on_transparent() {
+    let mut lines = Lines::default();
+    let red = vec4(1., 0., 0., 0.5);
+    lines.add_polyline(&[vec2(-60., 20.), vec2(60., 20.)], LineStyle::new(8., red));
+    // crosses the red line, so there is 50% blue over 50% red
+    let blue = LineStyle::new(8., vec4(0., 0., 1., 0.5));
+    lines.add_polyline(&[vec2(0., -40.), vec2(0., 40.)], blue);
+    let camera = camera(vec2(0., 0.), 1. / 160.);
+    let mut image = Image::new(WIDTH, HEIGHT, vec4(0., 0., 0., 0.));
+    Raster::default().draw(&mut image, &lines, &camera);
+    // PNG has straight alpha: the color isn't darkened by its alpha
+    let (cx, cy) = (WIDTH / 2, HEIGHT / 2);
+    assert_eq!(image.pixel(cx - 40, cy - 20), [255, 0, 0, 128]);
+    assert_eq!(image.pixel(cx, cy + 20), [0, 0, 255, 128]);
+    // blue over red: alpha 0.75, color 2/3 blue and 1/3 red
+    assert_eq!(image.pixel(cx, cy - 20), [85, 0, 170, 192]);
+    check("translucent_on_transparent", image);
+}
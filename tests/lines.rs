@@ -0,0 +1,150 @@
+use std::ops::Range;
+
+use glam::{vec2, vec4};
+use lines::{
+    draw_lines::{Line, LineStyle, Lines, SegmentType},
+    dvec2::dvec2,
+};
+
+fn line(x: f32) -> Line {
+    Line::new(
+        SegmentType::All,
+        vec2(x, 0.),
+        vec2(x, 1.),
+        1.,
+        vec4(1., 0., 0., 1.),
+    )
+}
+
+#[test]
+fn extend_returns_handles_of_copies() {
+    let mut detached = Lines::default();
+    let style = LineStyle::new(1., vec4(0., 0., 0., 1.));
+    detached.add_polyline(&[vec2(0., 0.), vec2(10., 0.), vec2(10., 10.)], style);
+
+    let mut lines = Lines::default();
+    let first = lines.add(Line::new(
+        SegmentType::All,
+        vec2(-5., -5.),
+        vec2(5., 5.),
+        1.,
+        vec4(1., 0., 0., 1.),
+    ));
+    let handles = lines.extend(&detached);
+    assert_eq!(handles.len(), detached.len());
+    for (handle, line) in handles.iter().zip(detached.iter()) {
+        assert_eq!(lines.get(*handle).unwrap().position, line.position);
+    }
+
+    // handles stay valid when other lines are removed
+    lines.remove(first);
+    assert_eq!(lines.get(handles[1]).unwrap().dir, vec2(0., 10.));
+}
+
+#[test]
+fn odd_dash_pattern_is_repeated() {
+    let style = LineStyle::new(1., vec4(0., 0., 0., 1.));
+    assert_eq!(style.with_dash(&[5.], 1.).dash, vec4(5., 5., 0., 0.));
+    assert_eq!(style.with_dash(&[5., 2.], 1.).dash, vec4(5., 2., 0., 0.));
+    assert_eq!(
+        style.with_dash(&[5., 2., 1., 3.], 1.).dash,
+        vec4(5., 2., 1., 3.)
+    );
+    assert_eq!(style.with_dash(&[], 1.).dash, vec4(0., 0., 0., 0.));
+}
+
+#[test]
+#[should_panic(expected = "dash pattern of 3 values is 6 long")]
+fn dash_pattern_of_3_values_is_rejected() {
+    LineStyle::new(1., vec4(0., 0., 0., 1.)).with_dash(&[5., 2., 1.], 0.);
+}
+
+#[test]
+#[should_panic(expected = "dash pattern of 6 values")]
+fn dash_pattern_longer_than_4_is_rejected() {
+    LineStyle::new(1., vec4(0., 0., 0., 1.)).with_dash(&[5., 2., 1., 2., 3., 4.], 0.);
+}
+
+#[test]
+fn stale_handles_fail() {
+    let mut lines = Lines::default();
+    let first = lines.add(line(0.));
+    let second = lines.add(line(1.));
+    assert!(lines.remove(first).is_some());
+    assert!(lines.get(first).is_none());
+    assert!(lines.center(first).is_none());
+    assert!(!lines.update(first, line(5.)));
+    assert!(lines.remove(first).is_none());
+    assert_eq!(lines.len(), 1);
+
+    // the slot is reused, but the old handle doesn't point to the new line
+    let third = lines.add(line(2.));
+    assert!(lines.get(first).is_none());
+    assert_eq!(lines.get(third).unwrap().position, vec2(2., 0.5));
+
+    lines.clear();
+    assert!(lines.is_empty());
+    for handle in [second, third].iter() {
+        assert!(lines.get(*handle).is_none());
+        assert!(!lines.update(*handle, line(5.)));
+        assert!(lines.remove(*handle).is_none());
+    }
+}
+
+#[test]
+fn remove_moves_the_last_line() {
+    let mut lines = Lines::default();
+    let handles: Vec<_> = (0..4).map(|i| lines.add(line(i as f32))).collect();
+    assert_eq!(lines.remove(handles[1]).unwrap().position, vec2(1., 0.5));
+    // the last line takes the place of the removed one
+    let xs: Vec<f32> = lines.iter().map(|line| line.position.x()).collect();
+    assert_eq!(xs, [0., 3., 2.]);
+    // and its handle still resolves
+    assert_eq!(lines.get(handles[3]).unwrap().position, vec2(3., 0.5));
+    assert_eq!(lines.center(handles[3]), Some(dvec2(3., 0.5)));
+    assert!(lines.update(handles[3], line(7.)));
+    assert_eq!(lines.iter().nth(1).unwrap().position, vec2(7., 0.5));
+}
+
+#[test]
+fn update_keeps_f64_center() {
+    let mut lines = Lines::default();
+    let style = LineStyle::new(1., vec4(0., 0., 0., 1.));
+    let handle = lines.add_polyline(&[dvec2(1e9, 0.25), dvec2(1e9 + 1., 0.25)], style)[0];
+    let center = dvec2(1e9 + 0.5, 0.25);
+    assert_eq!(lines.center(handle), Some(center));
+
+    // f32 position isn't changed, so the center stays precise
+    let mut line = *lines.get(handle).unwrap();
+    line.color = vec4(0., 1., 0., 1.);
+    assert!(lines.update(handle, line));
+    assert_eq!(lines.center(handle), Some(center));
+
+    // a new position is taken as is
+    line.position = vec2(10., 20.);
+    assert!(lines.update(handle, line));
+    assert_eq!(lines.center(handle), Some(dvec2(10., 20.)));
+}
+
+#[test]
+fn dirty_ranges_merge_and_clip() {
+    let mut lines = Lines::default();
+    let handles: Vec<_> = (0..5).map(|i| lines.add(line(i as f32))).collect();
+    // appends are one range
+    assert_eq!(lines.take_dirty(), vec![Range { start: 0, end: 5 }]);
+    assert!(lines.take_dirty().is_empty());
+
+    assert!(lines.update(handles[4], line(8.)));
+    assert!(lines.update(handles[1], line(8.)));
+    // the neighbour of the last change extends its range
+    assert!(lines.update(handles[2], line(8.)));
+    // the last line moves to 3, so the range 4..5 is out of the lines now
+    lines.remove(handles[3]);
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines.take_dirty(), vec![Range { start: 1, end: 4 }]);
+
+    // nothing to upload after clear
+    lines.update(handles[0], line(8.));
+    lines.clear();
+    assert!(lines.take_dirty().is_empty());
+}
@@ -0,0 +1,88 @@
+use glam::vec4;
+use lines::{
+    draw_lines::{LineStyle, Lines},
+    dvec2::{dvec2, DVec2},
+    lod::LodPyramid,
+};
+
+/// zigzag with teeth of the given height along 1000 world units
+fn zigzag(height: f64) -> Vec<DVec2> {
+    (0..=1000)
+        .map(|i| dvec2(i as f64, if i % 2 == 0 { 0. } else { height }))
+        .collect()
+}
+
+#[test]
+fn levels_get_coarser() {
+    let style = LineStyle::new(1., vec4(0., 0., 0., 1.));
+    let mut lod = LodPyramid::new(1., 5);
+    lod.add_polyline(&zigzag(0.5), style);
+    lod.add_polyline(&zigzag(3.), style);
+    // smaller than tolerance of the levels from 2 (2 world units)
+    lod.add_polygon(&[dvec2(0., 0.), dvec2(1.5, 0.), dvec2(0., 1.5)], style);
+
+    let tolerances: Vec<f64> = lod.levels().iter().map(|level| level.tolerance).collect();
+    assert_eq!(tolerances, vec![0., 1., 2., 4., 8.]);
+    let segments: Vec<usize> = lod.levels().iter().map(|level| level.len()).collect();
+    // small teeth are gone from level 1, the triangle from level 2, big teeth from level 3
+    assert_eq!(segments[..2], [2003, 1 + 1000 + 3]);
+    assert!(segments[2] <= 1000);
+    assert_eq!(segments[3..], [2, 2]);
+}
+
+#[test]
+fn level_for_zoom() {
+    let lod = LodPyramid::new(1., 5);
+    // 1024 world units on 1024 pixels
+    let zoom = 1. / 1024.;
+    assert_eq!(lod.level_for(zoom, 1024., 1.), 1);
+    assert_eq!(lod.level_for(zoom, 1024., 0.5), 0);
+    assert_eq!(lod.level_for(zoom / 3., 1024., 1.), 2);
+    // the coarsest level is the limit
+    assert_eq!(lod.level_for(1e-9, 1000., 1.), 4);
+}
+
+#[test]
+fn level_lines_are_made_like_lines() {
+    let style = LineStyle::new(1., vec4(0., 0., 0., 1.)).with_dash(&[2., 1.], 0.5);
+    let mut lod = LodPyramid::new(1., 3);
+    let mut lines = Lines::default();
+    let square = [
+        dvec2(4e6, 7e6),
+        dvec2(4e6 + 10., 7e6),
+        dvec2(4e6 + 10., 7e6 + 10.),
+        dvec2(4e6, 7e6 + 10.),
+        dvec2(4e6, 7e6),
+    ];
+    lod.add_polygon(&square, style);
+    lines.add_polygon(&square, style);
+    // repeated points are skipped
+    let zigzag: Vec<DVec2> = zigzag(3.).into_iter().flat_map(|p| vec![p, p]).collect();
+    lod.add_polyline(&zigzag, style);
+    lines.add_polyline(&zigzag, style);
+
+    let level = lod.level_lines(0);
+    assert_eq!(level.len(), lines.len());
+    for (i, (a, b)) in level.iter().zip(lines.iter()).enumerate() {
+        assert_eq!(
+            (a.segment_type, a.position, a.dir, a.distance),
+            (b.segment_type, b.position, b.dir, b.distance)
+        );
+        assert_eq!(
+            (a.prev_dir, a.next_dir, a.dash),
+            (b.prev_dir, b.next_dir, b.dash)
+        );
+        assert_eq!(lod.line(0, i).0.position, a.position);
+    }
+    // simplified lines keep distances along the original polyline for dashes
+    let mut lod = LodPyramid::new(1., 2);
+    let bump = [
+        dvec2(0., 0.),
+        dvec2(5., 0.5),
+        dvec2(10., 0.),
+        dvec2(10., 10.),
+    ];
+    lod.add_polyline(&bump, style);
+    let coarse: Vec<f32> = lod.lines(1).map(|(line, _)| line.distance).collect();
+    assert_eq!(coarse, [0., 2. * 25.25f32.sqrt()]);
+}
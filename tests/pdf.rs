@@ -0,0 +1,135 @@
+use glam::{vec2, vec4};
+use lines::{
+    draw_lines::{LineStyle, Lines},
+    dvec2::dvec2,
+    pdf::{Page, Pdf},
+};
+
+/// (offsets of objects from xref table, startxref value)
+fn xref(pdf: &str) -> (Vec<usize>, usize) {
+    let start = pdf.rfind("startxref\n").unwrap() + "startxref\n".len();
+    let startxref: usize = pdf[start..].lines().next().unwrap().parse().unwrap();
+    let mut lines = pdf[startxref..].lines();
+    assert_eq!(lines.next(), Some("xref"));
+    let size: usize = lines.next().unwrap()[2..].parse().unwrap();
+    assert_eq!(lines.next(), Some("0000000000 65535 f "));
+    let offsets = lines
+        .take(size - 1)
+        .map(|entry| {
+            assert_eq!(
+                entry.len(),
+                19,
+                "xref entries are 20 bytes with the newline"
+            );
+            assert!(entry.ends_with(" 00000 n "));
+            entry[..10].parse().unwrap()
+        })
+        .collect();
+    (offsets, startxref)
+}
+
+fn lines() -> Lines {
+    let mut lines = Lines::default();
+    let style = LineStyle::new(0.25, vec4(0., 0., 0., 0.5));
+    lines.add_polyline(&[vec2(-0.0001, 10.), vec2(100.125, 1e-5)], style);
+    lines
+}
+
+#[test]
+fn xref_offsets_point_at_objects() {
+    let mut pdf = Pdf::new();
+    let view = (dvec2(0., 0.), dvec2(200., 100.));
+    for page in [Page::A4, Page::A3.landscape(), Page::LETTER].iter() {
+        pdf.add_page(*page, &lines(), view);
+    }
+    assert_eq!(pdf.pages_num(), 3);
+    let bytes = pdf.to_bytes();
+    let text = String::from_utf8(bytes).unwrap();
+    assert!(text.starts_with("%PDF-1.4\n"));
+    assert!(text.ends_with("%%EOF\n"));
+
+    let (offsets, _) = xref(&text);
+    // catalog, page tree, resources, page and content for every page
+    assert_eq!(offsets.len(), 3 + 2 * 3);
+    for (i, offset) in offsets.iter().enumerate() {
+        let header = format!("{} 0 obj\n", i + 1);
+        assert!(
+            text[*offset..].starts_with(&header),
+            "object {} isn't at offset {}",
+            i + 1,
+            offset
+        );
+    }
+    assert!(text.contains("/Kids [4 0 R 6 0 R 8 0 R] /Count 3"));
+    assert!(text.contains(&format!("/Size {} /Root 1 0 R", offsets.len() + 1)));
+}
+
+#[test]
+fn stream_lengths_match() {
+    let mut pdf = Pdf::new();
+    pdf.add_page(Page::A4, &lines(), (dvec2(0., 0.), dvec2(200., 100.)));
+    let text = String::from_utf8(pdf.to_bytes()).unwrap();
+    let start = text.find("/Length ").unwrap() + "/Length ".len();
+    let length: usize = text[start..text[start..].find(' ').unwrap() + start]
+        .parse()
+        .unwrap();
+    let stream = text.find("stream\n").unwrap() + "stream\n".len();
+    assert_eq!(&text[stream + length..stream + length + 9], "endstream");
+}
+
+#[test]
+fn numbers_are_short() {
+    // LETTER without margins shows the view 1:1 in points
+    let mut pdf = Pdf::new();
+    let page = Page::LETTER.with_margin(0.);
+    pdf.add_page(page, &lines(), (dvec2(0., 0.), dvec2(612., 792.)));
+    let text = String::from_utf8(pdf.to_bytes()).unwrap();
+    // trailing zeros are dropped, fractions are rounded to 0.001, -0 is 0
+    assert!(text.contains("/MediaBox [0 0 612 792]"));
+    assert!(text.contains(" 0.5 w "));
+    assert!(text.contains("0 10 m 100.125 0 l S"));
+    assert!(text.contains("/GS0 << /CA 0.502 /ca 0.502 >>"));
+
+    let mut pdf = Pdf::new();
+    pdf.add_page(Page::A4, &lines(), (dvec2(0., 0.), dvec2(200., 100.)));
+    let text = String::from_utf8(pdf.to_bytes()).unwrap();
+    assert!(text.contains("/MediaBox [0 0 595.28 841.89]"));
+    let stream = text.find("stream\n").unwrap();
+    let numbers: Vec<&str> = text[stream..text.find("endstream").unwrap()]
+        .split_whitespace()
+        .filter(|token| token.parse::<f32>().is_ok())
+        .collect();
+    assert!(numbers.len() > 10);
+    for number in numbers {
+        let decimals = number.split('.').nth(1).map_or(0, str::len);
+        assert!(
+            !number.contains('e') && decimals <= 3 && number != "-0",
+            "{}",
+            number
+        );
+    }
+}
+
+#[test]
+fn gradient_corners_are_filled() {
+    // LETTER without margins shows the view 1:1 in points
+    let mut lines = Lines::default();
+    let points = [vec2(0., 0.), vec2(10., 0.), vec2(10., 10.)];
+    let colors = [
+        vec4(1., 0., 0., 1.),
+        vec4(0., 1., 0., 1.),
+        vec4(0., 0., 1., 1.),
+    ];
+    lines.add_gradient_polyline(&points, &colors, LineStyle::new(1., colors[0]));
+    let mut pdf = Pdf::new();
+    let page = Page::LETTER.with_margin(0.);
+    pdf.add_page(page, &lines, (dvec2(0., 0.), dvec2(612., 792.)));
+    let text = String::from_utf8(pdf.to_bytes()).unwrap();
+    // the outer piece of the round join at the corner in the color of the vertex
+    let join = text.find(" 0 1 0 rg 10 0 m 10 -1 l ").unwrap();
+    assert!(text[join..]
+        .lines()
+        .next()
+        .unwrap()
+        .ends_with(" 11 0 c h f Q"));
+}
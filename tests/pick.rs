@@ -0,0 +1,173 @@
+use glam::{vec2, vec4, Vec4};
+use lines::{
+    camera::Camera,
+    draw_lines::{Line, LineCap, LineStyle, Lines, SegmentType, WidthMode},
+    dvec2::dvec2,
+    pick::pick,
+    raster::{Image, Raster},
+};
+use quad_rand as qrand;
+
+const WIDTH: f32 = 200.;
+const HEIGHT: f32 = 100.;
+
+/// 200 x 100 world units on the screen, a pixel is a world unit
+fn camera() -> Camera {
+    Camera {
+        zoom: 1. / WIDTH,
+        ..Default::default()
+    }
+}
+
+fn black() -> Vec4 {
+    vec4(0., 0., 0., 1.)
+}
+
+#[test]
+fn thickness_and_tolerance() {
+    let mut lines = Lines::default();
+    let line = lines.add(Line::new(
+        SegmentType::All,
+        vec2(-50., 0.),
+        vec2(50., 0.),
+        5.,
+        black(),
+    ));
+    let raster = Raster::default();
+    let pick = |point, tolerance| pick(&lines, &raster, &camera(), WIDTH, HEIGHT, point, tolerance);
+    assert_eq!(pick(vec2(0., 4.9), 0.), vec![line]);
+    assert!(pick(vec2(0., 6.), 0.).is_empty());
+    assert_eq!(pick(vec2(0., 6.), 2.), vec![line]);
+    // round cap
+    assert_eq!(pick(vec2(54., 0.), 0.), vec![line]);
+    assert!(pick(vec2(54., 4.), 0.).is_empty());
+}
+
+#[test]
+fn segment_type_discards_caps() {
+    let mut lines = Lines::default();
+    let all = lines.add(
+        Line::new(
+            SegmentType::All,
+            vec2(-50., 20.),
+            vec2(50., 20.),
+            5.,
+            black(),
+        )
+        .with_caps(LineCap::Square, LineCap::Square),
+    );
+    let no_first = lines.add(
+        Line::new(
+            SegmentType::NoFirst,
+            vec2(-50., -20.),
+            vec2(50., -20.),
+            5.,
+            black(),
+        )
+        .with_caps(LineCap::Square, LineCap::Square),
+    );
+    let raster = Raster::default();
+    let pick = |x, y| pick(&lines, &raster, &camera(), WIDTH, HEIGHT, vec2(x, y), 0.);
+    assert_eq!(pick(-53., 20.), vec![all]);
+    assert!(pick(-53., -20.).is_empty());
+    assert_eq!(pick(53., -20.), vec![no_first]);
+}
+
+#[test]
+fn closest_and_top_first() {
+    let mut lines = Lines::default();
+    let style = LineStyle::new(10., black());
+    let bottom = lines.add_polyline(&[vec2(-50., 0.), vec2(50., 0.)], style)[0];
+    let top = lines.add_polyline(&[vec2(0., -40.), vec2(0., 40.)], style)[0];
+    let thin = lines.add_polyline(
+        &[vec2(-50., 15.), vec2(50., 15.)],
+        LineStyle::new(1., black()),
+    )[0];
+    let raster = Raster::default();
+    let pick = |x, y, tolerance| {
+        pick(
+            &lines,
+            &raster,
+            &camera(),
+            WIDTH,
+            HEIGHT,
+            vec2(x, y),
+            tolerance,
+        )
+    };
+    // both thick lines are under the point, the one drawn later is on top
+    assert_eq!(pick(5., 5., 0.), vec![top, bottom]);
+    assert_eq!(pick(20., 12., 5.), vec![thin, bottom]);
+}
+
+#[test]
+fn pixels_width_and_far_coordinates() {
+    // Web Mercator like coordinates, the view is 2 km wide, a pixel is 10 m
+    let center = dvec2(4e6 + 0.3, 7e6 + 0.7);
+    let camera = Camera {
+        position2d: center,
+        zoom: 1. / 2000.,
+        ..Default::default()
+    };
+    let mut lines = Lines::default();
+    let road = lines.add_polyline(
+        &[center - dvec2(500., 0.), center + dvec2(500., 0.)],
+        LineStyle::new(2., black()),
+    )[0];
+    let raster = Raster {
+        width_mode: WidthMode::Pixels,
+        ..Default::default()
+    };
+    let pick = |dy| {
+        pick(
+            &lines,
+            &raster,
+            &camera,
+            WIDTH,
+            HEIGHT,
+            center + dvec2(0., dy),
+            0.,
+        )
+    };
+    // 2 pixels are 20 m
+    assert_eq!(pick(19.), vec![road]);
+    assert!(pick(21.).is_empty());
+}
+
+#[test]
+fn pick_matches_raster() {
+    qrand::srand(11);
+    let mut lines = Lines::default();
+    for _ in 0..6 {
+        let points: Vec<_> = (0..5)
+            .map(|_| vec2(qrand::gen_range(-90., 90.), qrand::gen_range(-45., 45.)))
+            .collect();
+        let style = LineStyle::new(qrand::gen_range(1., 6.), black())
+            .with_dash(&[12., 6.], 0.)
+            .with_cap(LineCap::Square);
+        lines.add_polyline(&points, style);
+    }
+    // no antialiasing, so a pixel is drawn only if its center is inside of a line
+    let raster = Raster {
+        aa_width: 0.,
+        ..Default::default()
+    };
+    let camera = camera();
+    let clear_color = vec4(1., 1., 1., 1.);
+    let mut image = Image::new(WIDTH as u32, HEIGHT as u32, clear_color);
+    raster.draw(&mut image, &lines, &camera);
+
+    let mut differences = 0;
+    for y in 0..HEIGHT as u32 {
+        for x in 0..WIDTH as u32 {
+            let drawn = image.pixel(x, y) != [255, 255, 255, 255];
+            let point = camera.unproject(x as f32 + 0.5, y as f32 + 0.5, WIDTH, HEIGHT);
+            let picked = !pick(&lines, &raster, &camera, WIDTH, HEIGHT, point, 0.).is_empty();
+            if drawn != picked {
+                differences += 1;
+            }
+        }
+    }
+    // float rounding at the very edges
+    assert!(differences <= 5, "{} pixels differ", differences);
+}
@@ -0,0 +1,63 @@
+use glam::{vec2, Vec2};
+use lines::{
+    camera::Camera,
+    dvec2::dvec2,
+    simplify::{douglas_peucker, visvalingam, Tolerance},
+};
+
+/// straight run with a small noise and one sharp corner at x = 50
+fn corner() -> Vec<Vec2> {
+    (0..=100)
+        .map(|i| {
+            let x = i as f32;
+            let noise = if i % 2 == 0 { 0.01 } else { -0.01 };
+            let y = if x <= 50. { 0. } else { x - 50. };
+            vec2(x, y + noise)
+        })
+        .collect()
+}
+
+#[test]
+fn douglas_peucker_keeps_corners() {
+    let points = corner();
+    let simplified = douglas_peucker(&points, 0.1);
+    assert_eq!(simplified, vec![points[0], points[50], points[100]]);
+    // nothing is removed with zero tolerance except points exactly on the line
+    assert_eq!(douglas_peucker(&points, 0.).len(), points.len());
+}
+
+#[test]
+fn visvalingam_keeps_corners() {
+    let points = corner();
+    // area of the triangle with a noisy point is up to 0.5 after removal of its neighbours
+    let simplified = visvalingam(&points, 1.);
+    assert_eq!(simplified, vec![points[0], points[50], points[100]]);
+    assert_eq!(visvalingam(&points, 0.).len(), points.len());
+}
+
+#[test]
+fn short_polylines_are_kept() {
+    let points = [vec2(0., 0.), vec2(1., 1.)];
+    assert_eq!(douglas_peucker(&points, 10.), points.to_vec());
+    assert_eq!(visvalingam(&points[..1], 10.), points[..1].to_vec());
+}
+
+#[test]
+fn pixel_tolerance() {
+    // 1000 world units on 500 pixels
+    let camera = Camera {
+        zoom: 0.001,
+        ..Default::default()
+    };
+    let tolerance = Tolerance::pixels(3., &camera, 500.);
+    assert!((tolerance.world() - 6.).abs() < 1e-6);
+
+    // far from the origin, where f32 can't keep the noise
+    let points: Vec<_> = corner()
+        .iter()
+        .map(|p| dvec2(2e7 + p.x() as f64 * 10., 1e7 + p.y() as f64 * 10.))
+        .collect();
+    let simplified = douglas_peucker(&points, tolerance);
+    assert_eq!(simplified, vec![points[0], points[50], points[100]]);
+    assert_eq!(douglas_peucker(&points, 0.05).len(), points.len());
+}
@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+
+use glam::vec4;
+use lines::{
+    camera::Camera,
+    draw_lines::{LineHandle, LineJoin, LineStyle, Lines, WidthMode},
+    dvec2::{dvec2, DVec2},
+    spatial::SpatialIndex,
+};
+use quad_rand as qrand;
+
+/// random short polylines around big Web Mercator like coordinates with handles of their lines,
+/// nothing is removed, so handles are in `Lines::iter` order
+fn random_lines(thickness: f32) -> (Lines, Vec<LineHandle>) {
+    qrand::srand(3);
+    let mut lines = Lines::default();
+    let mut handles = vec![];
+    let base = dvec2(4e6, 7e6);
+    for _ in 0..500 {
+        let mut p = base + dvec2(qrand::gen_range(-1e4, 1e4), qrand::gen_range(-1e4, 1e4));
+        let mut points = vec![p];
+        for _ in 0..5 {
+            p += dvec2(qrand::gen_range(-100., 100.), qrand::gen_range(-100., 100.));
+            points.push(p);
+        }
+        handles
+            .extend(lines.add_polyline(&points, LineStyle::new(thickness, vec4(0., 0., 0., 1.))));
+    }
+    (lines, handles)
+}
+
+/// Checks that `found` are the lines which segments with `extent` around intersect the rectangle.
+/// Lines closer than `rounding` to the rectangle edge may be found or not
+fn assert_found(
+    found: &[usize],
+    (lines, handles): &(Lines, Vec<LineHandle>),
+    min: DVec2,
+    max: DVec2,
+    extent: f64,
+    rounding: f64,
+) {
+    let found: HashSet<usize> = found.iter().copied().collect();
+    for (i, handle) in handles.iter().enumerate() {
+        let line = lines.get(*handle).unwrap();
+        let center = lines.center(*handle).unwrap();
+        let half = dvec2(
+            (line.dir.x().abs() / 2.) as f64 + extent,
+            (line.dir.y().abs() / 2.) as f64 + extent,
+        );
+        let (a, b) = (center - half, center + half);
+        // how far the bounds are from the rectangle, negative if they intersect
+        let gap = (a.x - max.x)
+            .max(min.x - b.x)
+            .max(a.y - max.y)
+            .max(min.y - b.y);
+        if gap.abs() > rounding {
+            assert_eq!(
+                found.contains(&i),
+                gap < 0.,
+                "line {} is {} from the rectangle",
+                i,
+                gap
+            );
+        }
+    }
+}
+
+#[test]
+fn query_matches_brute_force() {
+    let lines = random_lines(5.);
+    // thickness 5 with round joins and square caps sticking out by up to 2 thicknesses,
+    // miter joins by the miter limit
+    for (join, extent) in [(LineJoin::Round, 10.), (LineJoin::Miter(10.), 50.)].iter() {
+        let index = SpatialIndex::new(&lines.0, WidthMode::World, *join);
+        assert_eq!(index.len(), 2500);
+        for (min, max) in [
+            (dvec2(3.99e6, 6.99e6), dvec2(4.01e6, 7.01e6)),
+            (dvec2(4e6, 7e6), dvec2(4.003e6, 7.002e6)),
+            (
+                dvec2(4e6 - 7000., 7e6 + 500.),
+                dvec2(4e6 - 4000., 7e6 + 900.),
+            ),
+            (dvec2(0., 0.), dvec2(1., 1.)),
+        ]
+        .iter()
+        {
+            let found = index.query(*min, *max);
+            assert_found(&found, &lines, *min, *max, *extent, 1e-6);
+            assert!(found.windows(2).all(|w| w[0] < w[1]), "sorted");
+        }
+    }
+    assert!(
+        SpatialIndex::new(&Lines::default(), WidthMode::World, LineJoin::default())
+            .query(dvec2(-1., -1.), dvec2(1., 1.))
+            .is_empty()
+    );
+}
+
+#[test]
+fn visible_in_pixels() {
+    let lines = random_lines(3.);
+    let index = SpatialIndex::new(&lines.0, WidthMode::Pixels, LineJoin::default());
+    // 2000 world units on 1000 pixels, a pixel is 2 units
+    let camera = Camera {
+        position2d: dvec2(4e6, 7e6),
+        zoom: 1. / 2000.,
+        ..Default::default()
+    };
+    let visible = index.visible(&camera, 1000., 500.);
+    assert!(!visible.is_empty());
+    // the view is extended by 3 pixels * 2 for caps * 2 units,
+    // it's unprojected with f32 projection, so its edges are rounded
+    let extent = 12.;
+    assert_found(
+        &visible,
+        &lines,
+        dvec2(4e6 - 1000. - extent, 7e6 - 500. - extent),
+        dvec2(4e6 + 1000. + extent, 7e6 + 500. + extent),
+        0.,
+        0.01,
+    );
+}
@@ -0,0 +1,146 @@
+use glam::{vec2, vec4, Vec4};
+use lines::{
+    draw_lines::{Line, LineCap, LineJoin, LineStyle, Lines, SegmentType, WidthMode},
+    dvec2::dvec2,
+    svg::Svg,
+    vector::data_bounds,
+};
+
+fn black() -> Vec4 {
+    vec4(0., 0., 0., 1.)
+}
+
+/// Values of attribute `name` in all elements, in document order
+fn attributes<'a>(svg: &'a str, name: &str) -> Vec<&'a str> {
+    let prefix = format!(" {}=\"", name);
+    svg.match_indices(&prefix)
+        .map(|(i, _)| {
+            let value = &svg[i + prefix.len()..];
+            &value[..value.find('"').unwrap()]
+        })
+        .collect()
+}
+
+#[test]
+fn view_box_is_relative_to_the_view() {
+    let mut lines = Lines::default();
+    let style = LineStyle::new(1., black());
+    lines.add_polyline(&[dvec2(3e7 + 10., 20.), dvec2(3e7 + 30., 40.)], style);
+    let view = (dvec2(3e7, 0.), dvec2(3e7 + 100., 50.));
+    let svg = Svg::default().export(&lines, view, 200., 100.);
+
+    assert!(svg.contains(r#"width="200" height="100" viewBox="0 -50 100 50""#));
+    // world y goes up, SVG y goes down
+    assert!(svg.contains(r#"<g transform="scale(1 -1)""#));
+    // coordinates are rebased on the view corner without f32 rounding of 3e7
+    assert_eq!(attributes(&svg, "x1"), ["10"]);
+    assert_eq!(attributes(&svg, "y1"), ["20"]);
+    assert_eq!(attributes(&svg, "x2"), ["30"]);
+    assert_eq!(attributes(&svg, "y2"), ["40"]);
+}
+
+#[test]
+fn polylines_become_paths_with_caps() {
+    let mut lines = Lines::default();
+    let points = [vec2(0., 0.), vec2(10., 0.), vec2(10., 10.)];
+    lines.add_polyline(
+        &points,
+        LineStyle::new(1., black()).with_cap(LineCap::Square),
+    );
+    let view = (dvec2(0., 0.), dvec2(20., 20.));
+    let svg = Svg::default().export(&lines, view, 100., 100.);
+    assert_eq!(attributes(&svg, "d"), ["M0 0 L10 0 L10 10"]);
+    assert_eq!(attributes(&svg, "stroke-linecap"), ["square"]);
+    assert_eq!(attributes(&svg, "stroke-width"), ["2"]);
+
+    // different caps are made of butt end and half disk
+    let mut lines = Lines::default();
+    let line = Line::new(SegmentType::All, vec2(0., 5.), vec2(10., 5.), 2., black())
+        .with_caps(LineCap::Butt, LineCap::Round);
+    lines.add(line);
+    let svg = Svg::default().export(&lines, view, 100., 100.);
+    assert_eq!(attributes(&svg, "stroke-linecap"), ["butt"]);
+    assert_eq!(attributes(&svg, "d")[0], "M10 7 A2 2 0 0 0 10 3 Z");
+}
+
+#[test]
+fn dashes_and_opacity() {
+    let mut lines = Lines::default();
+    let style = LineStyle::new(1., vec4(1., 0., 0., 0.5)).with_dash(&[4., 2., 1., 4.], 0.);
+    lines.add_polyline(&[vec2(0., 0.), vec2(10., 0.), vec2(10., 10.)], style);
+    let view = (dvec2(0., 0.), dvec2(20., 20.));
+    let svg = Svg::default().export(&lines, view, 100., 100.);
+    assert_eq!(attributes(&svg, "stroke"), ["#ff0000"]);
+    assert_eq!(attributes(&svg, "stroke-opacity"), ["0.5"]);
+    assert_eq!(attributes(&svg, "stroke-dasharray"), ["4 2 1 4"]);
+    assert_eq!(attributes(&svg, "stroke-dashoffset"), ["0"]);
+
+    // pixel width mode: thickness and dashes are scaled to world units in one pixel
+    let svg = Svg {
+        width_mode: WidthMode::Pixels,
+        ..Default::default()
+    }
+    .export(&lines, view, 40., 40.);
+    assert_eq!(attributes(&svg, "stroke-width"), ["1"]);
+    assert_eq!(attributes(&svg, "stroke-dasharray"), ["2 1 0.5 2"]);
+}
+
+fn gradient_corners() -> Lines {
+    let mut lines = Lines::default();
+    let points = [vec2(0., 0.), vec2(10., 0.), vec2(10., 10.), vec2(20., 10.)];
+    let colors = [
+        vec4(1., 0., 0., 0.5),
+        vec4(0., 1., 0., 0.5),
+        vec4(0., 0., 1., 0.5),
+        vec4(0., 0., 0., 0.5),
+    ];
+    lines.add_gradient_polyline(&points, &colors, LineStyle::new(1., black()));
+    lines
+}
+
+#[test]
+fn translucent_gradient_joins_fill_the_corners() {
+    let view = (dvec2(0., 0.), dvec2(20., 20.));
+    let svg = Svg::default().export(&gradient_corners(), view, 100., 100.);
+    // every segment is a stroke with its own gradient, their joined ends are butt
+    assert_eq!(svg.matches("<linearGradient").count(), 3);
+    assert_eq!(attributes(&svg, "stroke-linecap"), ["butt", "butt", "butt"]);
+    // pieces of round joins outside of the butt ends in the colors of the vertices,
+    // the first corner turns left, the second one right
+    let d = attributes(&svg, "d");
+    assert_eq!(d[0], "M10 0 L10 -1 A1 1 0 0 1 11 0 Z");
+    assert_eq!(d[2], "M10 10 L9 10 A1 1 0 0 0 10 11 Z");
+    let fill = attributes(&svg, "fill");
+    assert_eq!([fill[1], fill[3]], ["#00ff00", "#0000ff"]);
+    assert_eq!(attributes(&svg, "fill-opacity"), ["0.5"; 2]);
+    // and the round caps of the polyline ends
+    assert_eq!(
+        [d[1], d[3]],
+        ["M0 -1 A1 1 0 0 0 0 1 Z", "M20 11 A1 1 0 0 0 20 9 Z"]
+    );
+
+    let join = |join| {
+        let svg = Svg {
+            join,
+            ..Default::default()
+        }
+        .export(&gradient_corners(), view, 100., 100.);
+        attributes(&svg, "d")[0].to_string()
+    };
+    assert_eq!(join(LineJoin::Bevel), "M10 0 L10 -1 L11 0 Z");
+    assert_eq!(join(LineJoin::Miter(4.)), "M10 0 L10 -1 L11 -1 L11 0 Z");
+    // the miter is longer than the limit
+    assert_eq!(join(LineJoin::Miter(1.2)), "M10 0 L10 -1 L11 0 Z");
+}
+
+#[test]
+fn data_bounds_in_f64() {
+    let mut lines = Lines::default();
+    let style = LineStyle::new(1., black()).with_outline(0.5, black());
+    lines.add_polyline(&[dvec2(1e8, 5.), dvec2(1e8 + 10., -5.)], style);
+    let (min, max) = data_bounds(&lines, WidthMode::World).unwrap();
+    assert_eq!((min, max), (dvec2(1e8 - 1.5, -6.5), dvec2(1e8 + 11.5, 6.5)));
+    let (min, max) = data_bounds(&lines, WidthMode::Pixels).unwrap();
+    assert_eq!((min, max), (dvec2(1e8, -5.), dvec2(1e8 + 10., 5.)));
+    assert_eq!(data_bounds(&Lines::default(), WidthMode::World), None);
+}
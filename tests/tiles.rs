@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use glam::vec4;
+use lines::{
+    camera::Camera,
+    draw_lines::{LineJoin, LineStyle, WidthMode},
+    dvec2::dvec2,
+    lod::LodPyramid,
+    raster::Raster,
+    spatial::SpatialIndex,
+    tiles::TileSet,
+};
+use quad_rand as qrand;
+
+/// random walks around big Web Mercator like coordinates and a few long straight lines
+fn tile_set() -> TileSet {
+    qrand::srand(5);
+    let style = LineStyle::new(1., vec4(0., 0., 0., 1.));
+    let mut lod = LodPyramid::new(1., 8);
+    let base = dvec2(4e6, 7e6);
+    for _ in 0..300 {
+        let mut p = base + dvec2(qrand::gen_range(-2e4, 2e4), qrand::gen_range(-2e4, 2e4));
+        let mut points = vec![p];
+        for _ in 0..20 {
+            p += dvec2(qrand::gen_range(-50., 50.), qrand::gen_range(-50., 50.));
+            points.push(p);
+        }
+        lod.add_polyline(&points, style);
+    }
+    for i in 0..5 {
+        let y = 7e6 + (i as f64 - 2.) * 3000.;
+        lod.add_polyline(&[dvec2(4e6 - 2e4, y), dvec2(4e6 + 2e4, y + 500.)], style);
+    }
+    TileSet::new(lod)
+}
+
+#[test]
+fn visible_lines_are_drawn_once() {
+    let mut tiles = tile_set();
+    for (zoom, (width, height)) in [
+        (1. / 50000., (1000., 600.)),
+        (1. / 3000., (1000., 600.)),
+        (1. / 300., (800., 800.)),
+    ]
+    .iter()
+    {
+        let camera = Camera {
+            // on the long line in the middle
+            position2d: dvec2(4e6 + 700., 7e6 + 260.),
+            zoom: *zoom,
+            ..Default::default()
+        };
+        let keys = tiles.visible_tiles(&camera, *width, *height, 1.);
+        assert!(!keys.is_empty());
+        let level = keys[0].level;
+        let mut drawn = HashSet::new();
+        for key in keys {
+            for line in tiles.tile_lines(key) {
+                assert!(drawn.insert(line), "line {} is in two tiles", line);
+            }
+        }
+        let index = SpatialIndex::new(
+            &tiles.lod().level_lines(level),
+            WidthMode::World,
+            LineJoin::default(),
+        );
+        let visible = index.visible(&camera, *width, *height);
+        assert!(!visible.is_empty());
+        for line in visible {
+            assert!(drawn.contains(&line), "visible line {} isn't drawn", line);
+        }
+    }
+}
+
+#[test]
+fn zoom_and_level_follow_camera() {
+    let tiles = tile_set();
+    let camera = |zoom: f32| Camera {
+        zoom,
+        ..Default::default()
+    };
+    // the data square is about 40 km, the whole data fits into one tile
+    assert_eq!(tiles.zoom_for(&camera(1. / 50000.), 512.), 0);
+    let close = tiles.zoom_for(&camera(1. / 500.), 1000.);
+    let far = tiles.zoom_for(&camera(1. / 5000.), 1000.);
+    assert!(close > far && far > 0);
+}
+
+#[test]
+fn pick_finds_original_lines() {
+    let mut tiles = tile_set();
+    let camera = Camera {
+        position2d: dvec2(4e6 + 700., 7e6 + 260.),
+        zoom: 1. / 300.,
+        ..Default::default()
+    };
+    let raster = Raster::default();
+    // (found lines, found long lines)
+    let mut pick = |point, tolerance| {
+        let found = tiles.pick(&raster, &camera, 800., 800., point, tolerance);
+        let lod = tiles.lod();
+        let long = found.iter().filter(|i| lod.line(0, **i).0.dir.x() > 3e4);
+        (found.len(), long.count())
+    };
+    // on the long line in the middle, thickness 1 is almost 3 pixels
+    assert_eq!(pick(dvec2(4e6 + 700., 7e6 + 258.75), 1.).1, 1);
+    // 2 pixels off the edge
+    assert_eq!(pick(dvec2(4e6 + 700., 7e6 + 258.75 + 1.75), 1.).1, 0);
+    assert_eq!(pick(dvec2(4e6 + 700., 7e6 + 258.75 + 1.75), 3.).1, 1);
+    // out of the data
+    assert_eq!(pick(dvec2(4e6 + 3e4, 7e6), 3.), (0, 0));
+}